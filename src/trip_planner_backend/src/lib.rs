@@ -1,9 +1,9 @@
 #[macro_use]
 extern crate serde;
-use candid::{Decode, Encode};
+use candid::{Decode, Encode, Principal};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
-use std::{borrow::Cow, cell::RefCell};
+use std::{borrow::Cow, cell::RefCell, collections::BTreeMap, time::Duration};
 
 
 type Memory = VirtualMemory<DefaultMemoryImpl>; 
@@ -18,6 +18,13 @@ struct Location {
     site: String,
     description: String,
     activities: Vec<Activity>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    version: u64, // incremented on every change
+    created_at: u64,
+    updated_at: u64,
+    created_by: Principal,
+    updated_by: Principal,
 }
 
 #[derive (candid::CandidType, Clone,Serialize, Deserialize)]
@@ -25,10 +32,59 @@ struct Location {
 struct Activity {
     id:u64,
     name: String,
-    duration: u32,
+    duration: u32, // in hours
     cost: f64,
     description: String,
     location: u64,
+    category: ActivityCategory,
+    tags: Vec<String>,
+    opening_hours: Vec<OpeningHours>, // empty when always open
+    season: Option<Season>, // None when available all year
+    version: u64, // incremented on every change
+    created_at: u64,
+    updated_at: u64,
+    created_by: Principal,
+    updated_by: Principal,
+}
+
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum ActivityCategory {
+    Sightseeing,
+    Museum,
+    Food,
+    Nightlife,
+    Hiking,
+    Beach,
+    Sport,
+    Shopping,
+    Tour,
+    Other,
+}
+
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// opening window on a weekday, a weekday may have several windows
+struct OpeningHours {
+    weekday: Weekday,
+    open: String, // HH:MM
+    close: String, // HH:MM
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// months (1-12, inclusive) an activity is available, from_month > to_month wraps around new year
+struct Season {
+    from_month: u8,
+    to_month: u8,
 }
 #[derive (candid::CandidType, Clone,Serialize, Deserialize)]
 struct Trip {
@@ -36,10 +92,89 @@ struct Trip {
     name: String,
     start_date: String,
     end_date: String,
-    destinations: Vec<Location>,
+    destinations: Vec<Stop>,
     activities: Vec<Activity>,
     budget: f64,
     transportation: Vec<Transportation>,
+    schedule: Vec<ScheduledActivity>,
+    accommodations: Vec<Accommodation>,
+    bookings: Vec<Booking>, // activities and transportations without one are ideas
+    status: TripStatus, // changes are kept in `STATUS_HISTORY_STR`
+    version: u64, // incremented on every change, see `get_trip_versions`
+    created_at: u64,
+    updated_at: u64,
+    created_by: Principal,
+    updated_by: Principal,
+}
+
+// lifecycle of a trip, see `set_trip_status` for the allowed transitions
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum TripStatus {
+    Draft,
+    Planned,
+    Booked,
+    InProgress,
+    Completed,
+    Cancelled,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct StatusChange {
+    from: TripStatus,
+    to: TripStatus,
+    changed_at: u64,
+    changed_by: Principal,
+}
+
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum BookingStatus {
+    Idea,
+    Tentative,
+    Booked,
+    Paid,
+    Cancelled,
+}
+
+// activity or transportation of a trip that a booking is for
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum BookingItem {
+    Activity(u64),
+    Transportation(u64),
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for the booking state of an activity or transportation of a trip
+struct Booking {
+    item: BookingItem,
+    status: BookingStatus,
+    confirmation_code: Option<String>,
+    free_cancellation_until: Option<String>, // YYYY-MM-DD with an optional THH:MM
+    refundable_amount: f64, // what is paid back when cancelled
+}
+
+// timer that moves a trip to `status` at `at` (nanoseconds since the epoch)
+struct StatusTimer {
+    timer: ic_cdk_timers::TimerId,
+    status: TripStatus,
+    at: u64,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for a stop of a trip, stops are kept in arrival order
+struct Stop {
+    sequence: u32,
+    location: Location,
+    arrival_date: String,
+    departure_date: String,
+    accommodation: Option<u64>, // takes accommodation id
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for an activity placed on a day of the trip
+struct ScheduledActivity {
+    activity_id: u64,
+    date: String,
+    start_time: String,
 }
 
 #[derive (candid::CandidType, Clone,Serialize, Deserialize)]
@@ -51,63 +186,365 @@ struct Transportation {
     to: u64,
     cost: f64,
     date: String,
+    version: u64, // incremented on every change
+    created_at: u64,
+    updated_at: u64,
+    created_by: Principal,
+    updated_by: Principal,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for accommodations, a stay from check-in to check-out
+struct Accommodation {
+    id: u64,
+    name: String,
+    location_id: u64,
+    check_in: String,
+    check_out: String,
+    cost_per_night: f64,
+    currency: String,
+    confirmation_number: String,
+    address: String,
+    version: u64, // incremented on every change
+    created_at: u64,
+    updated_at: u64,
+    created_by: Principal,
+    updated_by: Principal,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for trip templates, a trip skeleton with days relative to its start
+// and without bookings, accommodations or transportation costs
+struct TripTemplate {
+    id: u64,
+    name: String,
+    days: u32, // days from the first to the last day of the trip
+    stops: Vec<TemplateStop>,
+    activities: Vec<u64>,
+    schedule: Vec<TemplateSlot>,
+    transportation: Vec<TemplateLeg>,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct TemplateStop {
+    location_id: u64,
+    arrival_day: u32,
+    nights: u32,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct TemplateSlot {
+    activity_id: u64,
+    day: u32,
+    start_time: String,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct TemplateLeg {
+    type_: String,
+    from: u64,
+    to: u64,
+    day: u32,
+    time: Option<String>, // HH:MM
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for a past version of a trip, kept under (trip id, version)
+struct TripVersion {
+    trip: Trip,
+    endpoint: String,
+    caller: Principal,
+    timestamp: u64,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for a deleted entity waiting in the trash, kept under (kind, id)
+struct TrashEntry {
+    kind: EntityKind,
+    id: u64,
+    deleted_at: u64,
+    deleted_by: Principal,
+    entity: TrashedEntity,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+enum TrashedEntity {
+    Location(Location),
+    Activity(Activity),
+    Trip(Trip),
+    Transportation(Transportation),
+    Accommodation(Accommodation),
+    Template(TripTemplate),
+    JournalEntry(JournalEntry),
+    PackingTemplate(PackingTemplate),
+}
+
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum NotificationKind {
+    CancellationDeadline,
+    CheckIn,
+    Departure,
+    BudgetThreshold,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for the notifications of a user's inbox, about the trips they created
+struct Notification {
+    id: u64,
+    recipient: Principal,
+    trip_id: u64,
+    kind: NotificationKind,
+    message: String,
+    created_at: u64,
+    read: bool,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for the notification preferences of a user
+struct NotificationPreferences {
+    cancellation_deadlines: bool,
+    check_ins: bool,
+    departures: bool,
+    budget: bool,
+    lead_time_hours: u32, // how early deadlines and check-ins are announced
+    budget_threshold_percent: u32, // share of the budget that triggers a warning
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        NotificationPreferences {
+            cancellation_deadlines: true,
+            check_ins: true,
+            departures: true,
+            budget: true,
+            lead_time_hours: 48,
+            budget_threshold_percent: 90,
+        }
+    }
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for audit log entries, `before` and `after` hold the changed fields of the entity as JSON
+struct AuditEntry {
+    id: u64,
+    caller: Principal,
+    timestamp: u64,
+    endpoint: String,
+    entity: EntityKind,
+    entity_id: u64,
+    before: Option<String>,
+    after: Option<String>,
+    snapshot_omitted: bool, // a changed side was too large to keep and is left out
+}
+
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum PackingCategory {
+    Clothing,
+    Footwear,
+    Toiletries,
+    Health,
+    Electronics,
+    Documents,
+    Gear,
+    Other,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for the packing list of a trip, kept under the trip id
+struct PackingList {
+    trip_id: u64,
+    items: Vec<PackingItem>,
+    next_item_id: u32,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct PackingItem {
+    id: u32,
+    name: String,
+    quantity: u32,
+    category: PackingCategory,
+    packed: bool,
+    traveler: Option<String>, // who carries it, everyone when not given
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for the to-do checklist of a trip, kept under the trip id
+struct Checklist {
+    trip_id: u64,
+    tasks: Vec<TripTask>,
+    next_task_id: u32,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct TripTask {
+    id: u32,
+    title: String,
+    due: Option<String>, // relative to the trip start date, e.g. "-30 days"
+    assignees: Vec<String>,
+    done: bool,
+    completed_at: Option<u64>,
+    completed_by: Option<Principal>,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for a task together with its trip and resolved due date
+struct TaskView {
+    trip_id: u64,
+    trip_name: String,
+    task: TripTask,
+    due_date: Option<String>,
+    overdue: bool,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for a journal entry written on a day of a trip
+struct JournalEntry {
+    id: u64,
+    trip_id: u64,
+    date: String, // YYYY-MM-DD, a day of the trip
+    location_id: Option<u64>,
+    activity_id: Option<u64>,
+    text: String,
+    mood: Option<Mood>,
+    rating: Option<u8>, // 1 to 5
+    tags: Vec<String>,
+    version: u64, // incremented on every change
+    created_at: u64,
+    updated_at: u64,
+    created_by: Principal,
+    updated_by: Principal,
+}
+
+#[derive (candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum Mood {
+    Great,
+    Good,
+    Okay,
+    Tired,
+    Bad,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for a day of a trip: the itinerary of that day and what was written about it
+struct DaySummary {
+    trip_id: u64,
+    date: String,
+    day: u32, // 1 for the start date
+    stops: Vec<Stop>,
+    activities: Vec<DayActivity>,
+    transportation: Vec<Transportation>,
+    accommodations: Vec<Accommodation>,
+    journal: Vec<JournalEntry>,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct DayActivity {
+    activity: Activity,
+    start_time: String,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for reusable packing lists
+struct PackingTemplate {
+    id: u64,
+    name: String,
+    items: Vec<PackingItemPayload>,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+// struct for the secret that grants read access to a trip over HTTP
+struct ShareToken {
+    token: String,
+    created_at: u64,
+}
+
+// bookkeeping fields shared by the stored entities
+trait Tracked {
+    fn id(&self) -> u64;
+    fn created(&self) -> (u64, Principal);
+    fn updated(&self) -> (u64, Principal);
+    // mark a change by the caller: bump the version and the updated_* fields
+    fn touch(&mut self);
+}
+
+macro_rules! impl_tracked {
+    ($($entity:ty),*) => {
+        $(impl Tracked for $entity {
+            fn id(&self) -> u64 {
+                self.id
+            }
+            fn created(&self) -> (u64, Principal) {
+                (self.created_at, self.created_by)
+            }
+            fn updated(&self) -> (u64, Principal) {
+                (self.updated_at, self.updated_by)
+            }
+            fn touch(&mut self) {
+                self.version += 1;
+                self.updated_at = ic_cdk::api::time();
+                self.updated_by = ic_cdk::caller();
+            }
+        })*
+    };
 }
 
+impl_tracked!(Location, Activity, Trip, Transportation, Accommodation, JournalEntry);
+
 // Implement the Storable and BoundedStorable traits for the Location struct
 impl Storable for Location {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
       Cow::Owned(Encode!(self).unwrap())
   }
 
-  fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
       Decode!(bytes.as_ref(), Self).unwrap()
   }
 }
 
 impl  BoundedStorable for Location {
-   const MAX_SIZE: u32 = 1024;
+   const MAX_SIZE: u32 = 16 * 1024;
   const IS_FIXED_SIZE: bool = false;
 }
 
 // Implement the Storable and BoundedStorable traits for the Activity struct
 impl Storable for Activity {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
       Cow::Owned(Encode!(self).unwrap())
   }
 
-  fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
       Decode!(bytes.as_ref(), Self).unwrap()
   }
 }
 
 impl  BoundedStorable for Activity {
-   const MAX_SIZE: u32 = 1024;
+   const MAX_SIZE: u32 = 2048;
   const IS_FIXED_SIZE: bool = false;
 }
 
 // Implement the Storable and BoundedStorable traits for the Trip struct
 impl Storable for Trip {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
       Cow::Owned(Encode!(self).unwrap())
   }
 
-  fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
       Decode!(bytes.as_ref(), Self).unwrap()
   }
 }
 
 impl  BoundedStorable for Trip {
-   const MAX_SIZE: u32 = 1024;
+   const MAX_SIZE: u32 = 16 * 1024;
   const IS_FIXED_SIZE: bool = false;
 }
 
 // Implement the Storable and BoundedStorable traits for the Transportation struct
 impl Storable for Transportation {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
       Cow::Owned(Encode!(self).unwrap())
   }
 
-  fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
       Decode!(bytes.as_ref(), Self).unwrap()
   }
 }
@@ -117,671 +554,6369 @@ impl  BoundedStorable for Transportation {
   const IS_FIXED_SIZE: bool = false;
 }
 
-thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
-    static LOCATION_ID: RefCell<IdCell> = RefCell::new(
-        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
-            .expect("Cannot create a counter")
-    );
-    static ACTIVITY_ID: RefCell<IdCell> = RefCell::new(
-        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))), 0)
-            .expect("Cannot create a counter")
-    );
-    static TRIP_ID: RefCell<IdCell> = RefCell::new(
-        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))), 0)
-            .expect("Cannot create a counter")
-    );
-    static TRANSPORTATION_ID: RefCell<IdCell> = RefCell::new(
-        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))), 0)
-            .expect("Cannot create a counter")
-    );
-    static LOCATION_STR: RefCell<StableBTreeMap<u64, Location, Memory>> = RefCell::new(
-        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))))
-    );
-    static ACTIVITY_STR: RefCell<StableBTreeMap<u64, Activity, Memory>> = RefCell::new(
-        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))))
-    );
-    static TRIP_STR: RefCell<StableBTreeMap<u64, Trip, Memory>> = RefCell::new(
-        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))))
-    );
-    static TRANSPORTATION_STR: RefCell<StableBTreeMap<u64, Transportation, Memory>> = RefCell::new(
-        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))))
-    );
+// Implement the Storable and BoundedStorable traits for the Accommodation struct
+impl Storable for Accommodation {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
 
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
 
+impl  BoundedStorable for Accommodation {
+   const MAX_SIZE: u32 = 1024;
+  const IS_FIXED_SIZE: bool = false;
 }
 
+// Implement the Storable and BoundedStorable traits for the TripTemplate struct
+impl Storable for TripTemplate {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
 
-// location payload
-#[derive(candid::CandidType,Serialize, Deserialize)]
-struct LocationPayload {
-    name: String,
-    country: String,
-    site: String,
-    description: String,
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
 }
 
-// activity payload
-#[derive(candid::CandidType,Serialize, Deserialize)]
-struct ActivityPayload {
-    name: String,
-    duration: u32,
-    cost: f64,
-    description: String,
-    location: u64,
+impl  BoundedStorable for TripTemplate {
+   const MAX_SIZE: u32 = 8 * 1024;
+  const IS_FIXED_SIZE: bool = false;
 }
 
-// trip payload
-#[derive(candid::CandidType,Serialize, Deserialize)]
-struct TripPayload {
-    name: String,
-    start_date: String,
-    end_date: String,
-    budget: f64,
+// Implement the Storable and BoundedStorable traits for the TripVersion struct
+impl Storable for TripVersion {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
 }
 
-// transportation payload
-#[derive(candid::CandidType,Serialize, Deserialize)]
-struct TransportationPayload {
-    type_: String,
-    from: u64,// takes location id
-    to: u64, // takes location id
-    cost: f64,
-    date: String,
+impl  BoundedStorable for TripVersion {
+   const MAX_SIZE: u32 = Trip::MAX_SIZE + 1024;
+  const IS_FIXED_SIZE: bool = false;
 }
 
-// function to  get all Locations 
-#[ic_cdk::query]
-fn get_locations() -> Result<Vec<Location>,Error> {
+// Implement the Storable and BoundedStorable traits for the TrashEntry struct
+impl Storable for TrashEntry {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
 
-    let locations = LOCATION_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
-    if locations.len() == 0 {
-        return Err(Error::NotFound { msg: "No Locations  found".to_string() });
-    }
-    Ok(locations)
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
 }
 
-//function get location by id 
-#[ic_cdk::query]
-fn get_location_by_id(id: u64) -> Result<Location,Error> {
-    LOCATION_STR.with(|service| {
-        service
-            .borrow_mut()
-            .get(&id)
-            .ok_or(Error::NotFound {
-                msg: format!("Location with the  id={} not found", id),
-            })
-    })
+impl  BoundedStorable for TrashEntry {
+   const MAX_SIZE: u32 = Trip::MAX_SIZE + 1024;
+  const IS_FIXED_SIZE: bool = false;
 }
 
-// function to add a location
-#[ic_cdk::update]
-fn add_location(payload: LocationPayload) -> Result<Location,Error> {
+// Implement the Storable and BoundedStorable traits for the Notification struct
+impl Storable for Notification {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
 
-    // validate user payload all fields are required
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
 
-    let id = LOCATION_ID
-    .with(|counter| {
-        let current_value = *counter.borrow().get();
-        counter.borrow_mut().set(current_value + 1)
-    })
-    .expect("cannot increment id counter");
+impl  BoundedStorable for Notification {
+   const MAX_SIZE: u32 = 1024;
+  const IS_FIXED_SIZE: bool = false;
+}
 
-    let location = Location {
-        id,
-        name: payload.name,
-        country: payload.country,
-        site: payload.site,
-        description: payload.description,
-        activities: Vec::new(),
-    };
+// Implement the Storable and BoundedStorable traits for the StatusChange struct
+impl Storable for StatusChange {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for StatusChange {
+   const MAX_SIZE: u32 = 256;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// stable map key for a user, the principal's raw bytes (at most 29)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct PrincipalKey(Principal);
+
+impl Storable for PrincipalKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_slice())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        PrincipalKey(Principal::from_slice(bytes.as_ref()))
+    }
+}
+
+impl BoundedStorable for PrincipalKey {
+    const MAX_SIZE: u32 = 29;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Implement the Storable and BoundedStorable traits for the NotificationPreferences struct
+impl Storable for NotificationPreferences {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for NotificationPreferences {
+   const MAX_SIZE: u32 = 128;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// Implement the Storable and BoundedStorable traits for the AuditEntry struct
+impl Storable for AuditEntry {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for AuditEntry {
+   const MAX_SIZE: u32 = 2 * AUDIT_MAX_SNAPSHOT_SIZE as u32 + 512;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// Implement the Storable and BoundedStorable traits for the PackingList struct
+impl Storable for PackingList {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for PackingList {
+   const MAX_SIZE: u32 = 16 * 1024;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// Implement the Storable and BoundedStorable traits for the Checklist struct
+impl Storable for Checklist {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for Checklist {
+   const MAX_SIZE: u32 = 16 * 1024;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// Implement the Storable and BoundedStorable traits for the JournalEntry struct
+impl Storable for JournalEntry {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for JournalEntry {
+   const MAX_SIZE: u32 = 8 * 1024;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// Implement the Storable and BoundedStorable traits for the PackingTemplate struct
+impl Storable for PackingTemplate {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for PackingTemplate {
+   const MAX_SIZE: u32 = 8 * 1024;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// Implement the Storable and BoundedStorable traits for the ShareToken struct
+impl Storable for ShareToken {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+      Cow::Owned(Encode!(self).unwrap())
+  }
+
+  fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+      Decode!(bytes.as_ref(), Self).unwrap()
+  }
+}
+
+impl  BoundedStorable for ShareToken {
+   const MAX_SIZE: u32 = 256;
+  const IS_FIXED_SIZE: bool = false;
+}
+
+// Records as the first release stored them. They stay on their memory ids (4 to 7)
+// and are moved into the current maps by `migrate_storage` on upgrade.
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct LegacyLocation {
+    id: u64,
+    name: String,
+    country: String,
+    site: String,
+    description: String,
+    activities: Vec<LegacyActivity>,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct LegacyActivity {
+    id:u64,
+    name: String,
+    duration: u32,
+    cost: f64,
+    description: String,
+    location: u64,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct LegacyTrip {
+    id:u64,
+    name: String,
+    start_date: String,
+    end_date: String,
+    destinations: Vec<LegacyLocation>,
+    activities: Vec<LegacyActivity>,
+    budget: f64,
+    transportation: Vec<LegacyTransportation>,
+}
+
+#[derive (candid::CandidType, Clone,Serialize, Deserialize)]
+struct LegacyTransportation {
+    id:u64,
+    type_: String,
+    from: u64,
+    to: u64,
+    cost: f64,
+    date: String,
+}
+
+macro_rules! impl_legacy_storable {
+    ($($record:ty),*) => {
+        $(impl Storable for $record {
+            fn to_bytes(&self) -> Cow<'_, [u8]> {
+                Cow::Owned(Encode!(self).unwrap())
+            }
+
+            fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+                Decode!(bytes.as_ref(), Self).unwrap()
+            }
+        }
+
+        // the size the first release created its maps with
+        impl BoundedStorable for $record {
+            const MAX_SIZE: u32 = 1024;
+            const IS_FIXED_SIZE: bool = false;
+        })*
+    };
+}
+
+impl_legacy_storable!(LegacyLocation, LegacyActivity, LegacyTrip, LegacyTransportation);
+
+// Version of the stable memory layout, see `migrate_storage`. A map checks on load
+// that `MAX_SIZE` of its types did not grow since it was created, so a type that needs
+// more room goes to a new map on a new memory id and a new version moves its records.
+const STORAGE_VERSION: u64 = 1;
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+    static LOCATION_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
+            .expect("Cannot create a counter")
+    );
+    static ACTIVITY_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))), 0)
+            .expect("Cannot create a counter")
+    );
+    static TRIP_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))), 0)
+            .expect("Cannot create a counter")
+    );
+    static TRANSPORTATION_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))), 0)
+            .expect("Cannot create a counter")
+    );
+    static LEGACY_LOCATION_STR: RefCell<StableBTreeMap<u64, LegacyLocation, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))))
+    );
+    static LEGACY_ACTIVITY_STR: RefCell<StableBTreeMap<u64, LegacyActivity, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))))
+    );
+    static LEGACY_TRIP_STR: RefCell<StableBTreeMap<u64, LegacyTrip, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))))
+    );
+    static LEGACY_TRANSPORTATION_STR: RefCell<StableBTreeMap<u64, LegacyTransportation, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))))
+    );
+    static SHARE_TOKEN_STR: RefCell<StableBTreeMap<u64, ShareToken, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))))
+    );
+    static ACCOMMODATION_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))), 0)
+            .expect("Cannot create a counter")
+    );
+    static ACCOMMODATION_STR: RefCell<StableBTreeMap<u64, Accommodation, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))))
+    );
+    static TEMPLATE_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))), 0)
+            .expect("Cannot create a counter")
+    );
+    static TEMPLATE_STR: RefCell<StableBTreeMap<u64, TripTemplate, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))))
+    );
+    static AUDIT_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))), 0)
+            .expect("Cannot create a counter")
+    );
+    static AUDIT_STR: RefCell<StableBTreeMap<u64, AuditEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))))
+    );
+    static TRIP_VERSION_STR: RefCell<StableBTreeMap<(u64, u64), TripVersion, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15))))
+    );
+    static TRASH_STR: RefCell<StableBTreeMap<(u8, u64), TrashEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16))))
+    );
+    static TRASH_RETENTION_DAYS: RefCell<Cell<u64, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17))), TRASH_DEFAULT_RETENTION_DAYS)
+            .expect("Cannot create the trash retention setting")
+    );
+    static NOTIFICATION_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))), 0)
+            .expect("Cannot create a counter")
+    );
+    static NOTIFICATION_STR: RefCell<StableBTreeMap<u64, Notification, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))))
+    );
+    // hash of each notification already sent, with the time it was sent
+    static NOTIFICATION_SENT: RefCell<StableBTreeMap<u64, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20))))
+    );
+    static NOTIFICATION_PREFERENCES: RefCell<StableBTreeMap<PrincipalKey, NotificationPreferences, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21))))
+    );
+    static PACKING_STR: RefCell<StableBTreeMap<u64, PackingList, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22))))
+    );
+    static PACKING_TEMPLATE_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))), 0)
+            .expect("Cannot create a counter")
+    );
+    static PACKING_TEMPLATE_STR: RefCell<StableBTreeMap<u64, PackingTemplate, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24))))
+    );
+    static CHECKLIST_STR: RefCell<StableBTreeMap<u64, Checklist, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25))))
+    );
+    static JOURNAL_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26))), 0)
+            .expect("Cannot create a counter")
+    );
+    static JOURNAL_STR: RefCell<StableBTreeMap<u64, JournalEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27))))
+    );
+    static STATUS_HISTORY_STR: RefCell<StableBTreeMap<(u64, u64), StatusChange, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28))))
+    );
+    static LOCATION_STR: RefCell<StableBTreeMap<u64, Location, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29))))
+    );
+    static ACTIVITY_STR: RefCell<StableBTreeMap<u64, Activity, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30))))
+    );
+    static TRIP_STR: RefCell<StableBTreeMap<u64, Trip, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31))))
+    );
+    static TRANSPORTATION_STR: RefCell<StableBTreeMap<u64, Transportation, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32))))
+    );
+    // 0 until the first upgrade from the first release
+    static STORAGE_VERSION_CELL: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(33))), 0)
+            .expect("Cannot create a counter")
+    );
+    // pending automatic status change of each trip, timers live on the heap only
+    static STATUS_TIMERS: RefCell<BTreeMap<u64, StatusTimer>> = const { RefCell::new(BTreeMap::new()) };
+
+
+}
+
+
+// location payload
+#[derive(candid::CandidType,Clone,Serialize, Deserialize)]
+struct LocationPayload {
+    name: String,
+    country: String,
+    site: String,
+    description: String,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+// activity payload, tags are stored trimmed and lowercase
+#[derive(candid::CandidType,Clone,Serialize, Deserialize)]
+struct ActivityPayload {
+    name: String,
+    duration: u32,
+    cost: f64,
+    description: String,
+    location: u64,
+    category: ActivityCategory,
+    tags: Vec<String>,
+    opening_hours: Vec<OpeningHours>,
+    season: Option<Season>,
+}
+
+impl LocationPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Location name is required".to_string() });
+        }
+        if self.country.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Location country is required".to_string() });
+        }
+        if self.latitude.is_some() != self.longitude.is_some() {
+            return Err(Error::InvalidInput { msg: "Latitude and longitude must be given together".to_string() });
+        }
+        if self.latitude.is_some_and(|lat| !(-90.0..=90.0).contains(&lat)) {
+            return Err(Error::InvalidInput { msg: "Latitude must be between -90 and 90".to_string() });
+        }
+        if self.longitude.is_some_and(|lon| !(-180.0..=180.0).contains(&lon)) {
+            return Err(Error::InvalidInput { msg: "Longitude must be between -180 and 180".to_string() });
+        }
+        Ok(())
+    }
+}
+
+impl TripPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Trip name is required".to_string() });
+        }
+        if parse_date(&self.end_date)? < parse_date(&self.start_date)? {
+            return Err(Error::InvalidInput { msg: "End date must not be before the start date".to_string() });
+        }
+        if !self.budget.is_finite() || self.budget < 0.0 {
+            return Err(Error::InvalidInput { msg: "Budget must be a non-negative number".to_string() });
+        }
+        Ok(())
+    }
+}
+
+impl TransportationPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.type_.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Transportation type is required".to_string() });
+        }
+        if !self.cost.is_finite() || self.cost < 0.0 {
+            return Err(Error::InvalidInput { msg: "Cost must be a non-negative number".to_string() });
+        }
+        parse_date_time(&self.date)?;
+        for location_id in [self.from, self.to] {
+            if !LOCATION_STR.with(|m| m.borrow().contains_key(&location_id)) {
+                return Err(Error::NotFound {
+                    msg: format!("Location with the id={} not found", location_id),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// longest activity in hours, it has to fit in one day
+const ACTIVITY_MAX_DURATION: u32 = 24;
+
+impl ActivityPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Activity name is required".to_string() });
+        }
+        if !(1..=ACTIVITY_MAX_DURATION).contains(&self.duration) {
+            return Err(Error::InvalidInput {
+                msg: format!("Activity duration must be between 1 and {} hours", ACTIVITY_MAX_DURATION),
+            });
+        }
+        if !self.cost.is_finite() || self.cost < 0.0 {
+            return Err(Error::InvalidInput { msg: "Activity cost must be a non-negative number".to_string() });
+        }
+        if !LOCATION_STR.with(|m| m.borrow().contains_key(&self.location)) {
+            return Err(Error::NotFound {
+                msg: format!("Location with the id={} not found", self.location),
+            });
+        }
+        if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+            return Err(Error::InvalidInput { msg: "Tags must not be empty".to_string() });
+        }
+        for hours in self.opening_hours.iter() {
+            if parse_time(&hours.close)? <= parse_time(&hours.open)? {
+                return Err(Error::InvalidInput {
+                    msg: format!("Opening hours on {:?} must close after they open", hours.weekday),
+                });
+            }
+        }
+        if let Some(season) = self.season.as_ref() {
+            if !(1..=12).contains(&season.from_month) || !(1..=12).contains(&season.to_month) {
+                return Err(Error::InvalidInput { msg: "Season months must be between 1 and 12".to_string() });
+            }
+        }
+        Ok(())
+    }
+}
+
+// trip payload
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TripPayload {
+    name: String,
+    start_date: String,
+    end_date: String,
+    budget: f64,
+}
+
+// transportation payload
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TransportationPayload {
+    type_: String,
+    from: u64,// takes location id
+    to: u64, // takes location id
+    cost: f64,
+    date: String,
+}
+
+// Patch payloads: only the fields that are given change. `Some(None)` clears an
+// optional field. Collections change through add/remove lists, removals first.
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct LocationPatch {
+    name: Option<String>,
+    country: Option<String>,
+    site: Option<String>,
+    description: Option<String>,
+    latitude: Option<Option<f64>>,
+    longitude: Option<Option<f64>>,
+    add_activities: Vec<u64>,
+    remove_activities: Vec<u64>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct ActivityPatch {
+    name: Option<String>,
+    duration: Option<u32>,
+    cost: Option<f64>,
+    description: Option<String>,
+    location: Option<u64>,
+    category: Option<ActivityCategory>,
+    add_tags: Vec<String>,
+    remove_tags: Vec<String>,
+    opening_hours: Option<Vec<OpeningHours>>,
+    season: Option<Option<Season>>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TripPatch {
+    name: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    budget: Option<f64>,
+    add_destinations: Vec<StopPayload>,
+    remove_destinations: Vec<u64>, // location ids
+    add_activities: Vec<u64>,
+    remove_activities: Vec<u64>,
+    add_transportation: Vec<u64>,
+    remove_transportation: Vec<u64>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TransportationPatch {
+    type_: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+    cost: Option<f64>,
+    date: Option<String>,
+}
+
+// booking payload, the status replaces any previous booking state of the item
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct BookingPayload {
+    item: BookingItem,
+    status: BookingStatus,
+    confirmation_code: Option<String>,
+    free_cancellation_until: Option<String>,
+    refundable_amount: f64,
+}
+
+// cost of the activities and transportations of a trip by booking state
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct BookingTotals {
+    committed: f64, // booked, paid and the non-refundable part of cancelled items
+    tentative: f64, // tentative bookings and ideas
+    cancellation_fees: f64, // the non-refundable part of cancelled items
+    refunded: f64, // the refundable part of cancelled items
+}
+
+// accommodation payload
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct AccommodationPayload {
+    name: String,
+    location_id: u64,
+    check_in: String, // YYYY-MM-DD
+    check_out: String, // YYYY-MM-DD
+    cost_per_night: f64,
+    currency: String,
+    confirmation_number: String,
+    address: String,
+}
+
+impl AccommodationPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Accommodation name is required".to_string() });
+        }
+        if !self.cost_per_night.is_finite() || self.cost_per_night < 0.0 {
+            return Err(Error::InvalidInput { msg: "Cost per night must be a non-negative number".to_string() });
+        }
+        if parse_date(&self.check_out)? <= parse_date(&self.check_in)? {
+            return Err(Error::InvalidInput { msg: "Check-out must be after check-in".to_string() });
+        }
+        if !LOCATION_STR.with(|m| m.borrow().contains_key(&self.location_id)) {
+            return Err(Error::NotFound {
+                msg: format!("Location with the id={} not found", self.location_id),
+            });
+        }
+        Ok(())
+    }
+}
+
+// filter of `get_activities_for_location`, empty fields match everything
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct ActivityFilter {
+    // matches any of the categories
+    categories: Vec<ActivityCategory>,
+    // matches activities carrying all of the tags
+    tags: Vec<String>,
+    // matches activities in season and open at some time on that date (YYYY-MM-DD)
+    available_on: Option<String>,
+}
+
+// payload to create a trip from a template
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TemplateInstancePayload {
+    name: String,
+    start_date: String, // YYYY-MM-DD
+    budget: f64,
+}
+
+// what the canister timers will do
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TimerState {
+    trash_purge_interval_seconds: u64,
+    notification_check_interval_seconds: u64,
+    status_changes: Vec<ScheduledStatusChange>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct ScheduledStatusChange {
+    trip_id: u64,
+    status: TripStatus,
+    at: u64,
+}
+
+// summary of a past version of a trip
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TripVersionInfo {
+    version: u64,
+    endpoint: String,
+    caller: Principal,
+    timestamp: u64,
+}
+
+// one change between two versions of a trip. `item` is the id of the changed
+// destination (location id), activity, transportation or accommodation, and
+// `before`/`after` are JSON values, missing when the item was added or removed
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct TripChange {
+    field: String,
+    item: Option<u64>,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+// filter and page of the audit log, entries are returned oldest first
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct AuditQuery {
+    entity: Option<EntityKind>,
+    entity_id: Option<u64>,
+    after: Option<u64>, // `next` of the previous page
+    limit: Option<u32>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct AuditPage {
+    entries: Vec<AuditEntry>,
+    next: Option<u64>,
+}
+
+// field to sort a list on, lists are sorted by id when not given
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize)]
+enum SortField {
+    Id,
+    CreatedAt,
+    UpdatedAt,
+}
+
+// filter and sort options of the get_* list queries, times are in nanoseconds
+#[derive(candid::CandidType, Clone, Default, Serialize, Deserialize)]
+struct ListOptions {
+    created_by: Option<Principal>,
+    updated_by: Option<Principal>,
+    created_after: Option<u64>,
+    created_before: Option<u64>,
+    updated_after: Option<u64>,
+    updated_before: Option<u64>,
+    sort_by: Option<SortField>,
+    descending: bool,
+}
+
+// packing item payload
+#[derive(candid::CandidType,Clone,Serialize, Deserialize)]
+struct PackingItemPayload {
+    name: String,
+    quantity: u32,
+    category: PackingCategory,
+    traveler: Option<String>,
+}
+
+impl PackingItemPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Packing item name is required".to_string() });
+        }
+        if self.quantity == 0 {
+            return Err(Error::InvalidInput { msg: "Packing item quantity must be at least 1".to_string() });
+        }
+        Ok(())
+    }
+}
+
+// journal entry payload, tags are stored trimmed and lowercase
+#[derive(candid::CandidType,Clone,Serialize, Deserialize)]
+struct JournalEntryPayload {
+    trip_id: u64,
+    date: String,
+    location_id: Option<u64>,
+    activity_id: Option<u64>,
+    text: String,
+    mood: Option<Mood>,
+    rating: Option<u8>,
+    tags: Vec<String>,
+}
+
+impl JournalEntryPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.text.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Journal entry text is required".to_string() });
+        }
+        if self.text.len() > 4000 {
+            return Err(Error::InvalidInput { msg: "Journal entry text must be at most 4000 bytes".to_string() });
+        }
+        if self.rating.is_some_and(|rating| !(1..=5).contains(&rating)) {
+            return Err(Error::InvalidInput { msg: "Rating must be between 1 and 5".to_string() });
+        }
+        let trip = get_trip_by_id(self.trip_id)?;
+        let day = parse_date(&self.date)?;
+        if day < parse_date(&trip.start_date)? || day > parse_date(&trip.end_date)? {
+            return Err(Error::InvalidInput {
+                msg: format!("Date {} is outside of the trip ({} to {})", self.date, trip.start_date, trip.end_date),
+            });
+        }
+        if let Some(location_id) = self.location_id {
+            get_location_by_id(location_id)?;
+        }
+        if let Some(activity_id) = self.activity_id {
+            get_activity_by_id(activity_id)?;
+        }
+        Ok(())
+    }
+}
+
+// task payload
+#[derive(candid::CandidType,Clone,Serialize, Deserialize)]
+struct TaskPayload {
+    title: String,
+    due: Option<String>,
+    assignees: Vec<String>,
+}
+
+impl TaskPayload {
+    fn validate(&self) -> Result<(),Error> {
+        if self.title.trim().is_empty() {
+            return Err(Error::InvalidInput { msg: "Task title is required".to_string() });
+        }
+        if let Some(due) = self.due.as_ref() {
+            parse_relative_days(due)?;
+        }
+        Ok(())
+    }
+}
+
+// stop payload
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct StopPayload {
+    location_id: u64,
+    arrival_date: String, // YYYY-MM-DD
+    departure_date: String, // YYYY-MM-DD
+    accommodation: Option<u64>, // takes the id of an accommodation of the trip
+}
+
+// schedule payload
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct SchedulePayload {
+    activity_id: u64,
+    date: String, // YYYY-MM-DD
+    start_time: String, // HH:MM
+}
+
+// kinds of stored entities
+#[derive(candid::CandidType, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum EntityKind {
+    Location,
+    Activity,
+    Trip,
+    Transportation,
+    Accommodation,
+    Template,
+    PackingTemplate,
+    JournalEntry,
+}
+
+// reference to an entity from a batch operation
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize)]
+enum IdRef {
+    // id of an existing entity
+    Id(u64),
+    // index of an earlier operation of the same batch, resolves to the id it produced
+    Op(u32),
+}
+
+// a single step of `apply_batch`, the optional refs override the ids inside the payload
+#[derive(candid::CandidType, Serialize, Deserialize)]
+enum Operation {
+    AddLocation(LocationPayload),
+    UpdateLocation { id: IdRef, payload: LocationPayload, expected_version: Option<u64> },
+    AddActivity { payload: ActivityPayload, location: Option<IdRef> },
+    UpdateActivity { id: IdRef, payload: ActivityPayload, location: Option<IdRef>, expected_version: Option<u64> },
+    AddTrip(TripPayload),
+    UpdateTrip { id: IdRef, payload: TripPayload, expected_version: Option<u64> },
+    AddTransportation { payload: TransportationPayload, from: Option<IdRef>, to: Option<IdRef> },
+    UpdateTransportation {
+        id: IdRef,
+        payload: TransportationPayload,
+        from: Option<IdRef>,
+        to: Option<IdRef>,
+        expected_version: Option<u64>,
+    },
+    AddActivityToLocation { location: IdRef, activity: IdRef },
+    AddDestinationToTrip { trip: IdRef, payload: StopPayload, location: Option<IdRef> },
+    AddActivityToTrip { trip: IdRef, activity: IdRef },
+    AddTransportationToTrip { trip: IdRef, transportation: IdRef },
+    ScheduleActivity { trip: IdRef, payload: SchedulePayload, activity: Option<IdRef> },
+}
+
+// outcome of one batch operation: the entity it created, updated or linked into
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct BatchResult {
+    kind: EntityKind,
+    id: u64,
+}
+
+#[derive(candid::CandidType, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum ItineraryIssueKind {
+    // a stop starts before or ends after the trip
+    OutsideTripDates,
+    // a stop starts before the previous one is left
+    Overlap,
+    // days between leaving a stop and arriving at the next one
+    Gap,
+    // no transportation of the trip goes from a stop to the next one
+    MissingTransport,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct ItineraryIssue {
+    kind: ItineraryIssueKind,
+    sequence: u32,
+    msg: String,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct ItineraryCheck {
+    // false when there is any issue other than a missing transport warning
+    valid: bool,
+    issues: Vec<ItineraryIssue>,
+}
+
+// preferences of `suggest_activities`
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct SuggestionPreferences {
+    // score of an activity of that category, categories not listed score 1
+    category_weights: Vec<(ActivityCategory, f64)>,
+    // added to the score of activities carrying the tag
+    tag_weights: Vec<(String, f64)>,
+    // hours per day spent on activities, 8 when not given
+    hours_per_day: Option<u32>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct LocationSuggestion {
+    location_id: u64,
+    free_hours: u32,
+    activities: Vec<Activity>,
+    cost: f64,
+    hours: u32,
+    score: f64,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct ActivitySuggestions {
+    remaining_budget: f64,
+    cost: f64,
+    score: f64,
+    locations: Vec<LocationSuggestion>,
+}
+
+#[derive(candid::CandidType, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum RouteObjective {
+    Cost,
+    Distance,
+}
+
+// options of `optimize_destination_order`
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct RouteOptions {
+    objective: RouteObjective,
+    // keep the current first stop first
+    fixed_start: bool,
+    // keep the current last stop last
+    fixed_end: bool,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct RouteLeg {
+    from: u64, // takes location id
+    to: u64, // takes location id
+    value: f64,
+    // false when taken from a transportation, true when derived from coordinates
+    estimated: bool,
+}
+
+// a destination order the user can accept with `reorder_trip_destinations`
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct DestinationOrderProposal {
+    objective: RouteObjective,
+    order: Vec<u64>,
+    legs: Vec<RouteLeg>,
+    total: f64,
+    current_total: f64,
+    // legs with neither a transportation nor coordinates to estimate them,
+    // they are counted with a penalty and the totals are not reliable
+    unknown_legs: u32,
+    // false when the order comes from a heuristic rather than an exhaustive search
+    optimal: bool,
+}
+
+// options of the CSV imports
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct CsvImportOptions {
+    dry_run: bool,
+    // (csv header, field name) pairs for headers that do not match a field name
+    columns: Vec<(String, String)>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct CsvRowError {
+    row: u64,
+    msg: String,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct LocationImportReport {
+    dry_run: bool,
+    locations: Vec<Location>,
+    errors: Vec<CsvRowError>,
+}
+
+#[derive(candid::CandidType,Serialize, Deserialize)]
+struct ActivityImportReport {
+    dry_run: bool,
+    activities: Vec<Activity>,
+    errors: Vec<CsvRowError>,
+}
+
+// request and response of the canister HTTP interface
+#[derive(candid::CandidType, Deserialize)]
+struct HttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(candid::CandidType, Serialize)]
+struct HttpResponse {
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+// function to  get all Locations 
+#[ic_cdk::query]
+fn get_locations(options: Option<ListOptions>) -> Result<Vec<Location>,Error> {
+
+    let locations = LOCATION_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
+    let locations = apply_list_options(locations, options.unwrap_or_default());
+    if locations.is_empty() {
+        return Err(Error::NotFound { msg: "No Locations  found".to_string() });
+    }
+    Ok(locations)
+}
+
+//function get location by id 
+#[ic_cdk::query]
+fn get_location_by_id(id: u64) -> Result<Location,Error> {
+    LOCATION_STR.with(|service| {
+        service
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Location with the  id={} not found", id),
+            })
+    })
+}
+
+// function to add a location
+#[ic_cdk::update]
+fn add_location(payload: LocationPayload) -> Result<Location,Error> {
+
+    payload.validate()?;
+
+    let id = LOCATION_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    let location = Location {
+        id,
+        name: payload.name,
+        country: payload.country,
+        site: payload.site,
+        description: payload.description,
+        activities: Vec::new(),
+        latitude: payload.latitude,
+        longitude: payload.longitude,
+        version: 1,
+        created_at: ic_cdk::api::time(),
+        updated_at: ic_cdk::api::time(),
+        created_by: ic_cdk::caller(),
+        updated_by: ic_cdk::caller(),
+    };
+
+    LOCATION_STR.with(|m| m.borrow_mut().insert(id, location.clone()));
+    record_audit("add_location", EntityKind::Location, id, None, Some(&location));
+    Ok(location)
+}
+
+// function to update a location
+#[ic_cdk::update]
+fn update_location(id: u64, payload: LocationPayload, expected_version: Option<u64>) -> Result<Location,Error> {
+    payload.validate()?;
+    LOCATION_STR.with(|m| {
+        let mut location = m
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Location with the id={} not found", id),
+            })?;
+        check_version("Location", id, location.version, expected_version)?;
+
+        location.name = payload.name;
+        location.country = payload.country;
+        location.site = payload.site;
+        location.description = payload.description;
+        location.latitude = payload.latitude;
+        location.longitude = payload.longitude;
+
+        location.touch();
+        let before = m.borrow_mut().insert(id, location.clone());
+        record_audit("update_location", EntityKind::Location, id, before.as_ref(), Some(&location));
+        Ok(location)
+    })
+}
+
+// function to delete a location
+#[ic_cdk::update]
+fn delete_location(id: u64) -> Result<(),Error> {
+    LOCATION_STR.with(|m| {
+        m.borrow_mut()
+            .remove(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Location with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_location", EntityKind::Location, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::Location(before));
+            })
+    })
+}
+
+// function to get all activities
+#[ic_cdk::query]
+fn get_activities(options: Option<ListOptions>) -> Result<Vec<Activity>,Error> {
+
+    let activities = ACTIVITY_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
+    let activities = apply_list_options(activities, options.unwrap_or_default());
+    if activities.is_empty() {
+        return Err(Error::NotFound { msg: "No Activities  found".to_string() });
+    }
+    Ok(activities)
+}
+
+// function to get activity by id
+#[ic_cdk::query]
+fn get_activity_by_id(id: u64) -> Result<Activity,Error> {
+    ACTIVITY_STR.with(|service| {
+        service
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Activity with the  id={} not found", id),
+            })
+    })
+}
+
+// function to add an activity
+#[ic_cdk::update]
+fn add_activity(payload: ActivityPayload) -> Result<Activity,Error> {
+
+    payload.validate()?;
+
+    let id = ACTIVITY_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    let activity = Activity {
+        id,
+        name: payload.name,
+        duration: payload.duration,
+        cost: payload.cost,
+        description: payload.description,
+        location: payload.location,
+        category: payload.category,
+        tags: normalize_tags(payload.tags),
+        opening_hours: payload.opening_hours,
+        season: payload.season,
+        version: 1,
+        created_at: ic_cdk::api::time(),
+        updated_at: ic_cdk::api::time(),
+        created_by: ic_cdk::caller(),
+        updated_by: ic_cdk::caller(),
+    };
+
+    ACTIVITY_STR.with(|m| m.borrow_mut().insert(id, activity.clone()));
+    record_audit("add_activity", EntityKind::Activity, id, None, Some(&activity));
+    Ok(activity)
+}
+
+// function to update an activity
+
+#[ic_cdk::update]
+fn update_activity(id: u64, payload: ActivityPayload, expected_version: Option<u64>) -> Result<Activity,Error> {
+    payload.validate()?;
+    ACTIVITY_STR.with(|m| {
+        let mut activity = m
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Activity with the id={} not found", id),
+            })?;
+        check_version("Activity", id, activity.version, expected_version)?;
+
+        activity.name = payload.name;
+        activity.duration = payload.duration;
+        activity.cost = payload.cost;
+        activity.description = payload.description;
+        activity.location = payload.location;
+        activity.category = payload.category;
+        activity.tags = normalize_tags(payload.tags);
+        activity.opening_hours = payload.opening_hours;
+        activity.season = payload.season;
+
+        activity.touch();
+        let before = m.borrow_mut().insert(id, activity.clone());
+        record_audit("update_activity", EntityKind::Activity, id, before.as_ref(), Some(&activity));
+        Ok(activity)
+    })
+}
+
+// function to delete an activity
+#[ic_cdk::update]
+fn delete_activity(id: u64) -> Result<(),Error> {
+    ACTIVITY_STR.with(|m| {
+        m.borrow_mut()
+            .remove(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Activity with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_activity", EntityKind::Activity, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::Activity(before));
+            })
+    })
+}
+
+// function to get all trips
+#[ic_cdk::query]
+fn get_trips(options: Option<ListOptions>, status: Option<TripStatus>) -> Result<Vec<Trip>,Error> {
+
+    let trips = TRIP_STR.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, v)| v)
+            .filter(|trip| status.is_none() || status == Some(trip.status))
+            .collect::<Vec<_>>()
+    });
+    let trips = apply_list_options(trips, options.unwrap_or_default());
+    if trips.is_empty() {
+        return Err(Error::NotFound { msg: "No Trips  found".to_string() });
+    }
+    Ok(trips)
+}
+
+// function to get trip by id
+#[ic_cdk::query]
+fn get_trip_by_id(id: u64) -> Result<Trip,Error> {
+    TRIP_STR.with(|service| {
+        service
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the  id={} not found", id),
+            })
+    })
+}
+
+// function to add a trip
+#[ic_cdk::update]
+fn add_trip(payload: TripPayload) -> Result<Trip,Error> {
+
+    payload.validate()?;
+
+    let id = TRIP_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    let mut trip = Trip {
+        id,
+        name: payload.name,
+        start_date: payload.start_date,
+        end_date: payload.end_date,
+        destinations: Vec::new(),
+        activities: Vec::new(),
+        budget: payload.budget,
+        transportation: Vec::new(),
+        schedule: Vec::new(),
+        accommodations: Vec::new(),
+        bookings: Vec::new(),
+        status: TripStatus::Draft,
+        version: 0,
+        created_at: ic_cdk::api::time(),
+        updated_at: ic_cdk::api::time(),
+        created_by: ic_cdk::caller(),
+        updated_by: ic_cdk::caller(),
+    };
+
+    record_trip_version("add_trip", &mut trip)?;
+    TRIP_STR.with(|m| m.borrow_mut().insert(id, trip.clone()));
+    record_audit("add_trip", EntityKind::Trip, id, None, Some(&trip));
+    Ok(trip)
+}
+
+// function to update a trip
+#[ic_cdk::update]
+fn update_trip(id: u64, payload: TripPayload, expected_version: Option<u64>) -> Result<Trip,Error> {
+    payload.validate()?;
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", id),
+            })?;
+        check_trip_editable(&trip)?;
+        check_version("Trip", id, trip.version, expected_version)?;
+
+        trip.name = payload.name;
+        trip.start_date = payload.start_date;
+        trip.end_date = payload.end_date;
+        trip.budget = payload.budget;
+        check_trip_dates(&trip)?;
+
+        record_trip_version("update_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(id, trip.clone());
+        record_audit("update_trip", EntityKind::Trip, id, before.as_ref(), Some(&trip));
+        Ok(trip)
+    })
+}
+
+// function to delete a trip
+#[ic_cdk::update]
+fn delete_trip(id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        m.borrow_mut()
+            .remove(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_trip", EntityKind::Trip, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::Trip(before));
+            })
+    })?;
+    SHARE_TOKEN_STR.with(|m| m.borrow_mut().remove(&id));
+    disarm_status_timer(id);
+    Ok(())
+}
+
+// function to get all transportation
+#[ic_cdk::query]
+fn get_transportations(options: Option<ListOptions>) -> Result<Vec<Transportation>,Error> {
+
+    let transportations = TRANSPORTATION_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
+    let transportations = apply_list_options(transportations, options.unwrap_or_default());
+    if transportations.is_empty() {
+        return Err(Error::NotFound { msg: "No Transportations  found".to_string() });
+    }
+    Ok(transportations)
+}
+
+// function to get transportation by id
+#[ic_cdk::query]
+fn get_transportation_by_id(id: u64) -> Result<Transportation,Error> {
+    TRANSPORTATION_STR.with(|service| {
+        service
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Transportation with the  id={} not found", id),
+            })
+    })
+}
+
+// function to add a transportation
+#[ic_cdk::update]
+fn add_transportation(payload: TransportationPayload) -> Result<Transportation,Error> {
+
+    payload.validate()?;
+
+    let id = TRANSPORTATION_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    let transportation = Transportation {
+        id,
+        type_: payload.type_,
+        from: payload.from,
+        to: payload.to,
+        cost: payload.cost,
+        date: payload.date,
+        version: 1,
+        created_at: ic_cdk::api::time(),
+        updated_at: ic_cdk::api::time(),
+        created_by: ic_cdk::caller(),
+        updated_by: ic_cdk::caller(),
+    };
+
+    TRANSPORTATION_STR.with(|m| m.borrow_mut().insert(id, transportation.clone()));
+    record_audit("add_transportation", EntityKind::Transportation, id, None, Some(&transportation));
+    Ok(transportation)
+}
+
+// function to update a transportation
+#[ic_cdk::update]
+fn update_transportation(id: u64, payload: TransportationPayload, expected_version: Option<u64>) -> Result<Transportation,Error> {
+    payload.validate()?;
+    TRANSPORTATION_STR.with(|m| {
+        let mut transportation = m
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Transportation with the id={} not found", id),
+            })?;
+        check_version("Transportation", id, transportation.version, expected_version)?;
+
+        transportation.type_ = payload.type_;
+        transportation.from = payload.from;
+        transportation.to = payload.to;
+        transportation.cost = payload.cost;
+        transportation.date = payload.date;
+
+        transportation.touch();
+        let before = m.borrow_mut().insert(id, transportation.clone());
+        record_audit("update_transportation", EntityKind::Transportation, id, before.as_ref(), Some(&transportation));
+        Ok(transportation)
+    })
+}
+
+// function to delete a transportation
+#[ic_cdk::update]
+fn delete_transportation(id: u64) -> Result<(),Error> {
+    TRANSPORTATION_STR.with(|m| {
+        m.borrow_mut()
+            .remove(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Transportation with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_transportation", EntityKind::Transportation, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::Transportation(before));
+            })
+    })
+}
+
+// function to get all accommodations
+#[ic_cdk::query]
+fn get_accommodations(options: Option<ListOptions>) -> Result<Vec<Accommodation>,Error> {
+
+    let accommodations = ACCOMMODATION_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
+    let accommodations = apply_list_options(accommodations, options.unwrap_or_default());
+    if accommodations.is_empty() {
+        return Err(Error::NotFound { msg: "No Accommodations  found".to_string() });
+    }
+    Ok(accommodations)
+}
+
+// function to get accommodation by id
+#[ic_cdk::query]
+fn get_accommodation_by_id(id: u64) -> Result<Accommodation,Error> {
+    ACCOMMODATION_STR.with(|service| {
+        service
+            .borrow()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Accommodation with the  id={} not found", id),
+            })
+    })
+}
+
+// function to add an accommodation
+#[ic_cdk::update]
+fn add_accommodation(payload: AccommodationPayload) -> Result<Accommodation,Error> {
+
+    payload.validate()?;
+
+    let id = ACCOMMODATION_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    let accommodation = Accommodation {
+        id,
+        name: payload.name,
+        location_id: payload.location_id,
+        check_in: payload.check_in,
+        check_out: payload.check_out,
+        cost_per_night: payload.cost_per_night,
+        currency: payload.currency,
+        confirmation_number: payload.confirmation_number,
+        address: payload.address,
+        version: 1,
+        created_at: ic_cdk::api::time(),
+        updated_at: ic_cdk::api::time(),
+        created_by: ic_cdk::caller(),
+        updated_by: ic_cdk::caller(),
+    };
+
+    ACCOMMODATION_STR.with(|m| m.borrow_mut().insert(id, accommodation.clone()));
+    record_audit("add_accommodation", EntityKind::Accommodation, id, None, Some(&accommodation));
+    Ok(accommodation)
+}
+
+// function to update an accommodation
+#[ic_cdk::update]
+fn update_accommodation(id: u64, payload: AccommodationPayload, expected_version: Option<u64>) -> Result<Accommodation,Error> {
+    payload.validate()?;
+    ACCOMMODATION_STR.with(|m| {
+        let mut accommodation = m
+            .borrow_mut()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Accommodation with the id={} not found", id),
+            })?;
+        check_version("Accommodation", id, accommodation.version, expected_version)?;
+
+        accommodation.name = payload.name;
+        accommodation.location_id = payload.location_id;
+        accommodation.check_in = payload.check_in;
+        accommodation.check_out = payload.check_out;
+        accommodation.cost_per_night = payload.cost_per_night;
+        accommodation.currency = payload.currency;
+        accommodation.confirmation_number = payload.confirmation_number;
+        accommodation.address = payload.address;
+
+        accommodation.touch();
+        let before = m.borrow_mut().insert(id, accommodation.clone());
+        record_audit("update_accommodation", EntityKind::Accommodation, id, before.as_ref(), Some(&accommodation));
+        Ok(accommodation)
+    })
+}
+
+// function to delete an accommodation
+#[ic_cdk::update]
+fn delete_accommodation(id: u64) -> Result<(),Error> {
+    ACCOMMODATION_STR.with(|m| {
+        m.borrow_mut()
+            .remove(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Accommodation with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_accommodation", EntityKind::Accommodation, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::Accommodation(before));
+            })
+    })
+}
+
+// Function to move a trip through its lifecycle:
+// Draft -> Planned -> Booked -> InProgress -> Completed, a Planned trip may start
+// without bookings, Planned and Booked may step back, and any trip that is not
+// Completed can be Cancelled. Completed and Cancelled trips can no longer be edited.
+#[ic_cdk::update]
+fn set_trip_status(trip_id: u64, status: TripStatus) -> Result<Trip,Error> {
+    let mut trip = get_trip_by_id(trip_id)?;
+    if !status_transition_allowed(trip.status, status) {
+        return Err(Error::InvalidInput {
+            msg: format!("Trip with the id={} cannot go from {:?} to {:?}", trip_id, trip.status, status),
+        });
+    }
+    change_trip_status(&mut trip, status, "set_trip_status")?;
+    Ok(trip)
+}
+
+// function to get the status changes of a trip, oldest first
+#[ic_cdk::query]
+fn get_trip_status_history(trip_id: u64) -> Result<Vec<StatusChange>,Error> {
+    get_trip_by_id(trip_id)?;
+    Ok(STATUS_HISTORY_STR.with(|m| {
+        m.borrow()
+            .range((trip_id, 0)..=(trip_id, u64::MAX))
+            .map(|(_, change)| change)
+            .collect()
+    }))
+}
+
+fn status_transition_allowed(from: TripStatus, to: TripStatus) -> bool {
+    use TripStatus::*;
+    matches!(
+        (from, to),
+        (Draft, Planned)
+            | (Planned, Draft)
+            | (Planned, Booked)
+            | (Booked, Planned)
+            | (Planned, InProgress)
+            | (Booked, InProgress)
+            | (InProgress, Completed)
+            | (Draft | Planned | Booked | InProgress, Cancelled)
+    )
+}
+
+// record a status change and store the trip
+fn change_trip_status(trip: &mut Trip, status: TripStatus, endpoint: &str) -> Result<(),Error> {
+    let change = StatusChange {
+        from: trip.status,
+        to: status,
+        changed_at: ic_cdk::api::time(),
+        changed_by: ic_cdk::caller(),
+    };
+    trip.status = status;
+    record_trip_version(endpoint, trip)?;
+    let before = TRIP_STR.with(|m| m.borrow_mut().insert(trip.id, trip.clone()));
+    record_audit(endpoint, EntityKind::Trip, trip.id, before.as_ref(), Some(&*trip));
+    STATUS_HISTORY_STR.with(|m| {
+        let mut history = m.borrow_mut();
+        let seq = history.range((trip.id, 0)..=(trip.id, u64::MAX)).count() as u64;
+        history.insert((trip.id, seq), change);
+    });
+    Ok(())
+}
+
+fn check_trip_editable(trip: &Trip) -> Result<(),Error> {
+    match trip.status {
+        TripStatus::Completed | TripStatus::Cancelled => Err(Error::InvalidInput {
+            msg: format!("Trip with the id={} is {:?} and can no longer be changed", trip.id, trip.status),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// function to change some fields of a location
+#[ic_cdk::update]
+fn patch_location(id: u64, patch: LocationPatch, expected_version: Option<u64>) -> Result<Location,Error> {
+    let mut location = get_location_by_id(id)?;
+    check_version("Location", id, location.version, expected_version)?;
+
+    let payload = LocationPayload {
+        name: patch.name.unwrap_or(location.name),
+        country: patch.country.unwrap_or(location.country),
+        site: patch.site.unwrap_or(location.site),
+        description: patch.description.unwrap_or(location.description),
+        latitude: patch.latitude.unwrap_or(location.latitude),
+        longitude: patch.longitude.unwrap_or(location.longitude),
+    };
+    payload.validate()?;
+    location.name = payload.name;
+    location.country = payload.country;
+    location.site = payload.site;
+    location.description = payload.description;
+    location.latitude = payload.latitude;
+    location.longitude = payload.longitude;
+
+    for activity_id in patch.remove_activities {
+        if !location.activities.iter().any(|a| a.id == activity_id) {
+            return Err(Error::NotFound {
+                msg: format!("Activity with the id={} is not part of location id={}", activity_id, id),
+            });
+        }
+        location.activities.retain(|a| a.id != activity_id);
+    }
+    for activity_id in patch.add_activities {
+        let activity = get_activity_by_id(activity_id)?;
+        if location.activities.iter().any(|a| a.id == activity_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Activity with the id={} is already part of location id={}", activity_id, id),
+            });
+        }
+        location.activities.push(activity);
+    }
+
+    location.touch();
+    let before = LOCATION_STR.with(|m| m.borrow_mut().insert(id, location.clone()));
+    record_audit("patch_location", EntityKind::Location, id, before.as_ref(), Some(&location));
+    Ok(location)
+}
+
+// function to change some fields of an activity
+#[ic_cdk::update]
+fn patch_activity(id: u64, patch: ActivityPatch, expected_version: Option<u64>) -> Result<Activity,Error> {
+    let mut activity = get_activity_by_id(id)?;
+    check_version("Activity", id, activity.version, expected_version)?;
+
+    let remove_tags = normalize_tags(patch.remove_tags);
+    let mut tags = activity.tags.clone();
+    tags.retain(|t| !remove_tags.contains(t));
+    tags.extend(patch.add_tags);
+    let payload = ActivityPayload {
+        name: patch.name.unwrap_or(activity.name),
+        duration: patch.duration.unwrap_or(activity.duration),
+        cost: patch.cost.unwrap_or(activity.cost),
+        description: patch.description.unwrap_or(activity.description),
+        location: patch.location.unwrap_or(activity.location),
+        category: patch.category.unwrap_or(activity.category),
+        tags,
+        opening_hours: patch.opening_hours.unwrap_or(activity.opening_hours),
+        season: patch.season.unwrap_or(activity.season),
+    };
+    payload.validate()?;
+    activity.name = payload.name;
+    activity.duration = payload.duration;
+    activity.cost = payload.cost;
+    activity.description = payload.description;
+    activity.location = payload.location;
+    activity.category = payload.category;
+    activity.tags = normalize_tags(payload.tags);
+    activity.opening_hours = payload.opening_hours;
+    activity.season = payload.season;
+
+    activity.touch();
+    let before = ACTIVITY_STR.with(|m| m.borrow_mut().insert(id, activity.clone()));
+    record_audit("patch_activity", EntityKind::Activity, id, before.as_ref(), Some(&activity));
+    Ok(activity)
+}
+
+// function to change some fields of a trip and add or remove its destinations,
+// activities and transportations in one step
+#[ic_cdk::update]
+fn patch_trip(id: u64, patch: TripPatch, expected_version: Option<u64>) -> Result<Trip,Error> {
+    let mut trip = get_trip_by_id(id)?;
+    check_trip_editable(&trip)?;
+    check_version("Trip", id, trip.version, expected_version)?;
+
+    if let Some(name) = patch.name {
+        trip.name = name;
+    }
+    if let Some(start_date) = patch.start_date {
+        trip.start_date = start_date;
+    }
+    if let Some(end_date) = patch.end_date {
+        trip.end_date = end_date;
+    }
+    if let Some(budget) = patch.budget {
+        trip.budget = budget;
+    }
+
+    for location_id in patch.remove_destinations {
+        if !trip.destinations.iter().any(|s| s.location.id == location_id) {
+            return Err(Error::NotFound {
+                msg: format!("Location with the id={} is not a destination of trip id={}", location_id, id),
+            });
+        }
+        trip.destinations.retain(|s| s.location.id != location_id);
+    }
+    for activity_id in patch.remove_activities {
+        if !trip.activities.iter().any(|a| a.id == activity_id) {
+            return Err(Error::NotFound {
+                msg: format!("Activity with the id={} is not part of trip id={}", activity_id, id),
+            });
+        }
+        trip.activities.retain(|a| a.id != activity_id);
+        trip.bookings.retain(|b| b.item != BookingItem::Activity(activity_id));
+        trip.schedule.retain(|s| s.activity_id != activity_id);
+    }
+    for transportation_id in patch.remove_transportation {
+        if !trip.transportation.iter().any(|t| t.id == transportation_id) {
+            return Err(Error::NotFound {
+                msg: format!("Transportation with the id={} is not part of trip id={}", transportation_id, id),
+            });
+        }
+        trip.transportation.retain(|t| t.id != transportation_id);
+        trip.bookings.retain(|b| b.item != BookingItem::Transportation(transportation_id));
+    }
+
+    for stop in patch.add_destinations {
+        let location = get_location_by_id(stop.location_id)?;
+        if trip.destinations.iter().any(|s| s.location.id == stop.location_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Location with the id={} is already a destination of trip id={}", stop.location_id, id),
+            });
+        }
+        validate_stop_dates(&trip, &stop)?;
+        trip.destinations.push(Stop {
+            sequence: 0,
+            location,
+            arrival_date: stop.arrival_date,
+            departure_date: stop.departure_date,
+            accommodation: stop.accommodation,
+        });
+    }
+    for activity_id in patch.add_activities {
+        let activity = get_activity_by_id(activity_id)?;
+        if trip.activities.iter().any(|a| a.id == activity_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Activity with the id={} is already part of trip id={}", activity_id, id),
+            });
+        }
+        trip.activities.push(activity);
+    }
+    for transportation_id in patch.add_transportation {
+        let transportation = get_transportation_by_id(transportation_id)?;
+        if trip.transportation.iter().any(|t| t.id == transportation_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Transportation with the id={} is already part of trip id={}", transportation_id, id),
+            });
+        }
+        trip.transportation.push(transportation);
+    }
+    sort_stops(&mut trip.destinations);
+    // the merged trip must pass what `update_trip` checks
+    TripPayload {
+        name: trip.name.clone(),
+        start_date: trip.start_date.clone(),
+        end_date: trip.end_date.clone(),
+        budget: trip.budget,
+    }
+    .validate()?;
+    check_trip_dates(&trip)?;
+
+    record_trip_version("patch_trip", &mut trip)?;
+    let before = TRIP_STR.with(|m| m.borrow_mut().insert(id, trip.clone()));
+    record_audit("patch_trip", EntityKind::Trip, id, before.as_ref(), Some(&trip));
+    Ok(trip)
+}
+
+// function to change some fields of a transportation
+#[ic_cdk::update]
+fn patch_transportation(id: u64, patch: TransportationPatch, expected_version: Option<u64>) -> Result<Transportation,Error> {
+    let mut transportation = get_transportation_by_id(id)?;
+    check_version("Transportation", id, transportation.version, expected_version)?;
+
+    if let Some(type_) = patch.type_ {
+        transportation.type_ = type_;
+    }
+    if let Some(from) = patch.from {
+        transportation.from = from;
+    }
+    if let Some(to) = patch.to {
+        transportation.to = to;
+    }
+    if let Some(cost) = patch.cost {
+        transportation.cost = cost;
+    }
+    if let Some(date) = patch.date {
+        transportation.date = date;
+    }
+    // the merged transportation must pass what `update_transportation` checks
+    TransportationPayload {
+        type_: transportation.type_.clone(),
+        from: transportation.from,
+        to: transportation.to,
+        cost: transportation.cost,
+        date: transportation.date.clone(),
+    }
+    .validate()?;
+
+    transportation.touch();
+    let before = TRANSPORTATION_STR.with(|m| m.borrow_mut().insert(id, transportation.clone()));
+    record_audit("patch_transportation", EntityKind::Transportation, id, before.as_ref(), Some(&transportation));
+    Ok(transportation)
+}
+
+// function to add an activity to a location
+#[ic_cdk::update]
+fn add_activity_to_location(location_id: u64, activity_id: u64) -> Result<(),Error> {
+    LOCATION_STR.with(|m| {
+        let mut location = m
+            .borrow_mut()
+            .get(&location_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Location with the id={} not found", location_id),
+            })?;
+
+        let activity = ACTIVITY_STR.with(|m| {
+            m.borrow_mut()
+                .get(&activity_id)
+                .ok_or(Error::NotFound {
+                    msg: format!("Activity with the id={} not found", activity_id),
+                })
+        })?;
+
+        if location.activities.iter().any(|a| a.id == activity_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Activity with the id={} is already part of location id={}", activity_id, location_id),
+            });
+        }
+
+        location.activities.push(activity.clone());
+        location.touch();
+        let before = m.borrow_mut().insert(location_id, location.clone());
+        record_audit("add_activity_to_location", EntityKind::Location, location_id, before.as_ref(), Some(&location));
+        Ok(())
+    })
+}
+
+// function to add a destination to a trip
+#[ic_cdk::update]
+fn add_destination_to_trip(trip_id: u64, payload: StopPayload) -> Result<(),Error> {
+    let location_id = payload.location_id;
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let location = LOCATION_STR.with(|m| {
+            m.borrow_mut()
+                .get(&location_id)
+                .ok_or(Error::NotFound {
+                    msg: format!("Location with the id={} not found", location_id),
+                })
+        })?;
+
+        if trip.destinations.iter().any(|s| s.location.id == location_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Location with the id={} is already a destination of trip id={}", location_id, trip_id),
+            });
+        }
+
+        validate_stop_dates(&trip, &payload)?;
+        trip.destinations.push(Stop {
+            sequence: 0,
+            location,
+            arrival_date: payload.arrival_date,
+            departure_date: payload.departure_date,
+            accommodation: payload.accommodation,
+        });
+        sort_stops(&mut trip.destinations);
+
+        record_trip_version("add_destination_to_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("add_destination_to_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to change the dates or accommodation of a stop of a trip
+#[ic_cdk::update]
+fn update_destination_in_trip(trip_id: u64, payload: StopPayload) -> Result<Trip,Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        validate_stop_dates(&trip, &payload)?;
+        let stop = trip
+            .destinations
+            .iter_mut()
+            .find(|s| s.location.id == payload.location_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Location with the id={} is not a destination of trip id={}", payload.location_id, trip_id),
+            })?;
+        stop.arrival_date = payload.arrival_date;
+        stop.departure_date = payload.departure_date;
+        stop.accommodation = payload.accommodation;
+        sort_stops(&mut trip.destinations);
+
+        record_trip_version("update_destination_in_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("update_destination_in_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(trip)
+    })
+}
+
+// function to add an activity to a trip
+#[ic_cdk::update]
+fn add_activity_to_trip(trip_id: u64, activity_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let activity = ACTIVITY_STR.with(|m| {
+            m.borrow_mut()
+                .get(&activity_id)
+                .ok_or(Error::NotFound {
+                    msg: format!("Activity with the id={} not found", activity_id),
+                })
+        })?;
+
+        if trip.activities.iter().any(|a| a.id == activity_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Activity with the id={} is already part of trip id={}", activity_id, trip_id),
+            });
+        }
+
+        trip.activities.push(activity.clone());
+        record_trip_version("add_activity_to_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("add_activity_to_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to schedule an activity of a trip on a given day and time
+#[ic_cdk::update]
+fn schedule_activity(trip_id: u64, payload: SchedulePayload) -> Result<Trip,Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let activity = trip
+            .activities
+            .iter()
+            .find(|a| a.id == payload.activity_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Activity with the id={} is not part of trip id={}", payload.activity_id, trip_id),
+            })?;
+
+        let day = parse_date(&payload.date)?;
+        let start = parse_time(&payload.start_time)?;
+        if day < parse_date(&trip.start_date)? || day > parse_date(&trip.end_date)? {
+            return Err(Error::InvalidInput {
+                msg: format!("Date {} is outside of the trip dates", payload.date),
+            });
+        }
+        check_activity_availability(activity, day, Some(start))?;
+
+        // an activity has a single slot, scheduling it again moves it
+        trip.schedule.retain(|s| s.activity_id != payload.activity_id);
+        trip.schedule.push(ScheduledActivity {
+            activity_id: payload.activity_id,
+            date: payload.date,
+            start_time: payload.start_time,
+        });
+        trip.schedule.sort_by(|a, b| (&a.date, &a.start_time).cmp(&(&b.date, &b.start_time)));
+
+        record_trip_version("schedule_activity", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("schedule_activity", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(trip)
+    })
+}
+
+// function to remove an activity from the trip schedule
+#[ic_cdk::update]
+fn unschedule_activity(trip_id: u64, activity_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let before = trip.schedule.len();
+        trip.schedule.retain(|s| s.activity_id != activity_id);
+        if trip.schedule.len() == before {
+            return Err(Error::NotFound {
+                msg: format!("Activity with the id={} is not scheduled in trip id={}", activity_id, trip_id),
+            });
+        }
+
+        record_trip_version("unschedule_activity", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("unschedule_activity", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to add a transportation to a trip
+#[ic_cdk::update]
+fn add_transportation_to_trip(trip_id: u64, transportation_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let transportation = TRANSPORTATION_STR.with(|m| {
+            m.borrow_mut()
+                .get(&transportation_id)
+                .ok_or(Error::NotFound {
+                    msg: format!("Transportation with the id={} not found", transportation_id),
+                })
+        })?;
+
+        if trip.transportation.iter().any(|t| t.id == transportation_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Transportation with the id={} is already part of trip id={}", transportation_id, trip_id),
+            });
+        }
+
+        trip.transportation.push(transportation.clone());
+        record_trip_version("add_transportation_to_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("add_transportation_to_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to remove an activity from a location
+#[ic_cdk::update]
+fn remove_activity_from_location(location_id: u64, activity_id: u64) -> Result<(),Error> {
+    LOCATION_STR.with(|m| {
+        let mut location = m
+            .borrow_mut()
+            .get(&location_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Location with the id={} not found", location_id),
+            })?;
+
+        let before = location.activities.len();
+        location.activities.retain(|a| a.id != activity_id);
+        if location.activities.len() == before {
+            return Err(Error::NotFound {
+                msg: format!("Activity with the id={} is not part of location id={}", activity_id, location_id),
+            });
+        }
+        location.touch();
+
+        let before = m.borrow_mut().insert(location_id, location.clone());
+        record_audit("remove_activity_from_location", EntityKind::Location, location_id, before.as_ref(), Some(&location));
+        Ok(())
+    })
+}
+
+// function to remove a destination from a trip
+#[ic_cdk::update]
+fn remove_destination_from_trip(trip_id: u64, location_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let before = trip.destinations.len();
+        trip.destinations.retain(|s| s.location.id != location_id);
+        if trip.destinations.len() == before {
+            return Err(Error::NotFound {
+                msg: format!("Location with the id={} is not a destination of trip id={}", location_id, trip_id),
+            });
+        }
+        sort_stops(&mut trip.destinations);
+
+        record_trip_version("remove_destination_from_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("remove_destination_from_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to remove an activity (and its schedule slot) from a trip
+#[ic_cdk::update]
+fn remove_activity_from_trip(trip_id: u64, activity_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let before = trip.activities.len();
+        trip.activities.retain(|a| a.id != activity_id);
+        trip.bookings.retain(|b| b.item != BookingItem::Activity(activity_id));
+        if trip.activities.len() == before {
+            return Err(Error::NotFound {
+                msg: format!("Activity with the id={} is not part of trip id={}", activity_id, trip_id),
+            });
+        }
+        trip.schedule.retain(|s| s.activity_id != activity_id);
+
+        record_trip_version("remove_activity_from_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("remove_activity_from_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to remove a transportation from a trip
+#[ic_cdk::update]
+fn remove_transportation_from_trip(trip_id: u64, transportation_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let before = trip.transportation.len();
+        trip.transportation.retain(|t| t.id != transportation_id);
+        trip.bookings.retain(|b| b.item != BookingItem::Transportation(transportation_id));
+        if trip.transportation.len() == before {
+            return Err(Error::NotFound {
+                msg: format!("Transportation with the id={} is not part of trip id={}", transportation_id, trip_id),
+            });
+        }
+
+        record_trip_version("remove_transportation_from_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("remove_transportation_from_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to add an accommodation to a trip, it is linked to the stop at its location
+#[ic_cdk::update]
+fn add_accommodation_to_trip(trip_id: u64, accommodation_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let accommodation = get_accommodation_by_id(accommodation_id)?;
+
+        if trip.accommodations.iter().any(|a| a.id == accommodation_id) {
+            return Err(Error::AlreadyExists {
+                msg: format!("Accommodation with the id={} is already part of trip id={}", accommodation_id, trip_id),
+            });
+        }
+        if parse_date(&accommodation.check_in)? < parse_date(&trip.start_date)?
+            || parse_date(&accommodation.check_out)? > parse_date(&trip.end_date)?
+        {
+            return Err(Error::InvalidInput {
+                msg: format!("Accommodation with the id={} is outside of the trip dates", accommodation_id),
+            });
+        }
+
+        for stop in trip.destinations.iter_mut() {
+            if stop.location.id == accommodation.location_id && stop.accommodation.is_none() {
+                stop.accommodation = Some(accommodation_id);
+            }
+        }
+        trip.accommodations.push(accommodation);
+        record_trip_version("add_accommodation_to_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("add_accommodation_to_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to remove an accommodation from a trip and its stops
+#[ic_cdk::update]
+fn remove_accommodation_from_trip(trip_id: u64, accommodation_id: u64) -> Result<(),Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let before = trip.accommodations.len();
+        trip.accommodations.retain(|a| a.id != accommodation_id);
+        if trip.accommodations.len() == before {
+            return Err(Error::NotFound {
+                msg: format!("Accommodation with the id={} is not part of trip id={}", accommodation_id, trip_id),
+            });
+        }
+        for stop in trip.destinations.iter_mut() {
+            if stop.accommodation == Some(accommodation_id) {
+                stop.accommodation = None;
+            }
+        }
+
+        record_trip_version("remove_accommodation_from_trip", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("remove_accommodation_from_trip", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(())
+    })
+}
+
+// function to set the order of the destinations of a trip,
+// `location_ids` must list every current destination exactly once.
+// Stops keep their number of nights and are laid out back to back,
+// starting on the arrival date of the current first stop.
+#[ic_cdk::update]
+fn reorder_trip_destinations(trip_id: u64, location_ids: Vec<u64>) -> Result<Trip,Error> {
+    TRIP_STR.with(|m| {
+        let mut trip = m
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+        check_trip_editable(&trip)?;
+
+        let mut current = trip.destinations.iter().map(|s| s.location.id).collect::<Vec<_>>();
+        let mut requested = location_ids.clone();
+        current.sort_unstable();
+        requested.sort_unstable();
+        if current != requested {
+            return Err(Error::InvalidInput {
+                msg: format!("The new order must list each destination of trip id={} exactly once", trip_id),
+            });
+        }
+
+        let mut cursor = match trip.destinations.first() {
+            Some(first) => parse_date(&first.arrival_date)?,
+            None => return Ok(trip),
+        };
+        let mut destinations = std::mem::take(&mut trip.destinations);
+        for id in location_ids {
+            let index = destinations.iter().position(|s| s.location.id == id).unwrap();
+            let mut stop = destinations.swap_remove(index);
+            let nights = parse_date(&stop.departure_date)? - parse_date(&stop.arrival_date)?;
+            stop.arrival_date = format_date(cursor);
+            stop.departure_date = format_date(cursor + nights);
+            cursor += nights;
+            trip.destinations.push(stop);
+        }
+        sort_stops(&mut trip.destinations);
+
+        record_trip_version("reorder_trip_destinations", &mut trip)?;
+        let before = m.borrow_mut().insert(trip_id, trip.clone());
+        record_audit("reorder_trip_destinations", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+        Ok(trip)
+    })
+}
+
+// function to get all activities for a location, optionally filtered
+#[ic_cdk::query]
+fn get_activities_for_location(location_id: u64, filter: Option<ActivityFilter>) -> Result<Vec<Activity>,Error> {
+    let location = LOCATION_STR.with(|service| {
+        service
+            .borrow_mut()
+            .get(&location_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Location with the id={} not found", location_id),
+            })
+    })?;
+
+    let filter = match filter {
+        Some(filter) => filter,
+        None => return Ok(location.activities),
+    };
+    let tags = normalize_tags(filter.tags);
+    let day = match filter.available_on.as_deref() {
+        Some(date) => Some(parse_date(date)?),
+        None => None,
+    };
+
+    Ok(location
+        .activities
+        .into_iter()
+        .filter(|a| filter.categories.is_empty() || filter.categories.contains(&a.category))
+        .filter(|a| tags.iter().all(|tag| a.tags.contains(tag)))
+        .filter(|a| match day {
+            Some(day) => check_activity_availability(a, day, None).is_ok(),
+            None => true,
+        })
+        .collect())
+}
+
+// function to get all destinations for a trip
+#[ic_cdk::query]
+fn get_destinations_for_trip(trip_id: u64) -> Result<Vec<Stop>,Error> {
+    TRIP_STR.with(|service| {
+        let trip = service
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+
+        Ok(trip.destinations.clone())
+    })
+}
+
+// check that the stops of a trip are inside the trip dates, do not overlap and
+// follow each other without gaps, and warn about consecutive stops that are not
+// connected by a transportation of the trip
+#[ic_cdk::query]
+fn check_trip_itinerary(trip_id: u64) -> Result<ItineraryCheck,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let trip_start = parse_date(&trip.start_date)?;
+    let trip_end = parse_date(&trip.end_date)?;
+    let mut issues = Vec::new();
+
+    let mut previous: Option<(&Stop, i64)> = None;
+    for stop in trip.destinations.iter() {
+        let arrival = parse_date(&stop.arrival_date)?;
+        let departure = parse_date(&stop.departure_date)?;
+        if arrival < trip_start || departure > trip_end {
+            issues.push(ItineraryIssue {
+                kind: ItineraryIssueKind::OutsideTripDates,
+                sequence: stop.sequence,
+                msg: format!("{} is outside of the trip dates", stop.location.name),
+            });
+        }
+
+        if let Some((prev, prev_departure)) = previous {
+            if arrival < prev_departure {
+                issues.push(ItineraryIssue {
+                    kind: ItineraryIssueKind::Overlap,
+                    sequence: stop.sequence,
+                    msg: format!("{} starts before {} is left", stop.location.name, prev.location.name),
+                });
+            } else if arrival > prev_departure {
+                issues.push(ItineraryIssue {
+                    kind: ItineraryIssueKind::Gap,
+                    sequence: stop.sequence,
+                    msg: format!(
+                        "{} day(s) between leaving {} and arriving at {}",
+                        arrival - prev_departure,
+                        prev.location.name,
+                        stop.location.name
+                    ),
+                });
+            }
+
+            let connected = trip
+                .transportation
+                .iter()
+                .any(|t| t.from == prev.location.id && t.to == stop.location.id);
+            if !connected {
+                issues.push(ItineraryIssue {
+                    kind: ItineraryIssueKind::MissingTransport,
+                    sequence: stop.sequence,
+                    msg: format!("No transportation from {} to {}", prev.location.name, stop.location.name),
+                });
+            }
+        }
+        previous = Some((stop, departure));
+    }
+
+    let valid = issues
+        .iter()
+        .all(|issue| issue.kind == ItineraryIssueKind::MissingTransport);
+    Ok(ItineraryCheck { valid, issues })
+}
+
+// check that the stops and scheduled activities of a trip are within its (new) dates
+fn check_trip_dates(trip: &Trip) -> Result<(),Error> {
+    let start = parse_date(&trip.start_date)?;
+    let end = parse_date(&trip.end_date)?;
+    for stop in trip.destinations.iter() {
+        if parse_date(&stop.arrival_date)? < start || parse_date(&stop.departure_date)? > end {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "Stop at {} ({} - {}) would be outside of the trip dates",
+                    stop.location.name, stop.arrival_date, stop.departure_date
+                ),
+            });
+        }
+    }
+    for slot in trip.schedule.iter() {
+        let day = parse_date(&slot.date)?;
+        if day < start || day > end {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "Activity with the id={} scheduled on {} would be outside of the trip dates",
+                    slot.activity_id, slot.date
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+// check a new or changed stop against the trip, its accommodations and its other stops
+fn validate_stop_dates(trip: &Trip, payload: &StopPayload) -> Result<(),Error> {
+    if let Some(accommodation_id) = payload.accommodation {
+        let at_stop = trip
+            .accommodations
+            .iter()
+            .any(|a| a.id == accommodation_id && a.location_id == payload.location_id);
+        if !at_stop {
+            return Err(Error::NotFound {
+                msg: format!(
+                    "Accommodation with the id={} is not part of the trip at location id={}",
+                    accommodation_id, payload.location_id
+                ),
+            });
+        }
+    }
+
+    let arrival = parse_date(&payload.arrival_date)?;
+    let departure = parse_date(&payload.departure_date)?;
+    if departure < arrival {
+        return Err(Error::InvalidInput {
+            msg: "Departure date must not be before the arrival date".to_string(),
+        });
+    }
+    if arrival < parse_date(&trip.start_date)? || departure > parse_date(&trip.end_date)? {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "Stop {} - {} is outside of the trip dates",
+                payload.arrival_date, payload.departure_date
+            ),
+        });
+    }
+
+    for stop in trip.destinations.iter() {
+        if stop.location.id == payload.location_id {
+            continue;
+        }
+        // sharing the travel day is fine, anything more is an overlap
+        let other_arrival = parse_date(&stop.arrival_date)?;
+        let other_departure = parse_date(&stop.departure_date)?;
+        if arrival < other_departure && other_arrival < departure {
+            return Err(Error::InvalidInput {
+                msg: format!(
+                    "Stop overlaps with {} ({} - {})",
+                    stop.location.name, stop.arrival_date, stop.departure_date
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+// keep stops in arrival order and number them from 1
+fn sort_stops(stops: &mut [Stop]) {
+    stops.sort_by(|a, b| {
+        (&a.arrival_date, &a.departure_date).cmp(&(&b.arrival_date, &b.departure_date))
+    });
+    for (index, stop) in stops.iter_mut().enumerate() {
+        stop.sequence = index as u32 + 1;
+    }
+}
+
+// function to get all activities for a trip
+#[ic_cdk::query]
+fn get_activities_for_trip(trip_id: u64) -> Result<Vec<Activity>,Error> {
+    TRIP_STR.with(|service| {
+        let trip = service
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+
+        Ok(trip.activities.clone())
+    })
+}
+
+// function to get all transportation for a trip
+#[ic_cdk::query]
+fn get_transportation_for_trip(trip_id: u64) -> Result<Vec<Transportation>,Error> {
+    TRIP_STR.with(|service| {
+        let trip = service
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+
+        Ok(trip.transportation.clone())
+    })
+}
+
+// get total transportation cost
+#[ic_cdk::query]
+fn get_total_transportation_cost(trip_id: u64) -> Result<f64,Error> {
+    TRIP_STR.with(|service| {
+        let trip = service
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+
+        let mut total_cost = 0.0;
+        for transportation in trip.transportation.iter() {
+            total_cost += booked_cost(&trip, BookingItem::Transportation(transportation.id), transportation.cost);
+        }
+
+        Ok(total_cost)
+    })
+}
+
+// get total activity cost
+#[ic_cdk::query]
+fn get_total_activity_cost(trip_id: u64) -> Result<f64,Error> {
+    TRIP_STR.with(|service| {
+        let trip = service
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+
+        let mut total_cost = 0.0;
+        for activity in trip.activities.iter() {
+            total_cost += booked_cost(&trip, BookingItem::Activity(activity.id), activity.cost);
+        }
+
+        Ok(total_cost)
+    })
+}
+
+// get total cost
+#[ic_cdk::query]
+fn get_total_cost(trip_id: u64) -> Result<f64,Error> {
+    TRIP_STR.with(|service| {
+        let trip = service
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+
+        let mut total_cost = 0.0;
+        for activity in trip.activities.iter() {
+            total_cost += booked_cost(&trip, BookingItem::Activity(activity.id), activity.cost);
+        }
+
+        for transportation in trip.transportation.iter() {
+            total_cost += booked_cost(&trip, BookingItem::Transportation(transportation.id), transportation.cost);
+        }
+
+        total_cost += accommodation_cost(&trip)?;
+
+        Ok(total_cost)
+    })
+}
+
+// get total accommodation cost
+#[ic_cdk::query]
+fn get_total_accommodation_cost(trip_id: u64) -> Result<f64,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    accommodation_cost(&trip)
+}
+
+// nightly costs of all accommodations of a trip, amounts are added as-is whatever their currency
+fn accommodation_cost(trip: &Trip) -> Result<f64,Error> {
+    let mut total_cost = 0.0;
+    for accommodation in trip.accommodations.iter() {
+        let nights = parse_date(&accommodation.check_out)? - parse_date(&accommodation.check_in)?;
+        total_cost += nights as f64 * accommodation.cost_per_night;
+    }
+    Ok(total_cost)
+}
+
+// get the nights of a trip (by the date they start on) without a booked accommodation
+#[ic_cdk::query]
+fn get_unbooked_nights(trip_id: u64) -> Result<Vec<String>,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let mut stays = Vec::new();
+    for accommodation in trip.accommodations.iter() {
+        stays.push((parse_date(&accommodation.check_in)?, parse_date(&accommodation.check_out)?));
+    }
+
+    let nights = parse_date(&trip.start_date)?..parse_date(&trip.end_date)?;
+    Ok(nights
+        .filter(|night| !stays.iter().any(|(check_in, check_out)| check_in <= night && night < check_out))
+        .map(format_date)
+        .collect())
+}
+
+// function to set the booking state of an activity or transportation of a trip
+#[ic_cdk::update]
+fn set_booking(trip_id: u64, payload: BookingPayload) -> Result<Trip,Error> {
+    let mut trip = get_trip_by_id(trip_id)?;
+    check_trip_editable(&trip)?;
+
+    let cost = item_cost(&trip, payload.item).ok_or(Error::NotFound {
+        msg: format!("{:?} is not part of trip id={}", payload.item, trip_id),
+    })?;
+    if !payload.refundable_amount.is_finite() || payload.refundable_amount < 0.0 || payload.refundable_amount > cost {
+        return Err(Error::InvalidInput {
+            msg: format!("Refundable amount must be between 0 and the cost {}", cost),
+        });
+    }
+    if let Some(deadline) = payload.free_cancellation_until.as_ref() {
+        parse_date_time(deadline)?;
+    }
+
+    trip.bookings.retain(|b| b.item != payload.item);
+    if payload.status != BookingStatus::Idea || payload.confirmation_code.is_some() || payload.free_cancellation_until.is_some() {
+        trip.bookings.push(Booking {
+            item: payload.item,
+            status: payload.status,
+            confirmation_code: payload.confirmation_code,
+            free_cancellation_until: payload.free_cancellation_until,
+            refundable_amount: payload.refundable_amount,
+        });
+    }
+
+    record_trip_version("set_booking", &mut trip)?;
+    let before = TRIP_STR.with(|m| m.borrow_mut().insert(trip_id, trip.clone()));
+    record_audit("set_booking", EntityKind::Trip, trip_id, before.as_ref(), Some(&trip));
+    Ok(trip)
+}
+
+// get the cost of a trip's activities and transportations split by booking state
+#[ic_cdk::query]
+fn get_booking_totals(trip_id: u64) -> Result<BookingTotals,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let items = trip
+        .activities
+        .iter()
+        .map(|a| (BookingItem::Activity(a.id), a.cost))
+        .chain(trip.transportation.iter().map(|t| (BookingItem::Transportation(t.id), t.cost)));
+
+    let mut totals = BookingTotals { committed: 0.0, tentative: 0.0, cancellation_fees: 0.0, refunded: 0.0 };
+    for (item, cost) in items {
+        match booking_of(&trip, item) {
+            Some(booking) if booking.status == BookingStatus::Cancelled => {
+                let fee = cost - booking.refundable_amount;
+                totals.committed += fee;
+                totals.cancellation_fees += fee;
+                totals.refunded += booking.refundable_amount;
+            }
+            Some(booking) if matches!(booking.status, BookingStatus::Booked | BookingStatus::Paid) => {
+                totals.committed += cost;
+            }
+            _ => totals.tentative += cost,
+        }
+    }
+    Ok(totals)
+}
+
+fn booking_of(trip: &Trip, item: BookingItem) -> Option<&Booking> {
+    trip.bookings.iter().find(|b| b.item == item)
+}
+
+fn item_cost(trip: &Trip, item: BookingItem) -> Option<f64> {
+    match item {
+        BookingItem::Activity(id) => trip.activities.iter().find(|a| a.id == id).map(|a| a.cost),
+        BookingItem::Transportation(id) => trip.transportation.iter().find(|t| t.id == id).map(|t| t.cost),
+    }
+}
+
+// what an item of a trip costs, a cancelled item costs what is not refunded
+fn booked_cost(trip: &Trip, item: BookingItem, cost: f64) -> f64 {
+    match booking_of(trip, item) {
+        Some(booking) if booking.status == BookingStatus::Cancelled => cost - booking.refundable_amount,
+        _ => cost,
+    }
+}
+
+// get total duration
+#[ic_cdk::query]
+fn get_total_duration(trip_id: u64) -> Result<u32,Error> {
+    TRIP_STR.with(|service| {
+        let trip = service
+            .borrow_mut()
+            .get(&trip_id)
+            .ok_or(Error::NotFound {
+                msg: format!("Trip with the id={} not found", trip_id),
+            })?;
+
+        let mut total_duration = 0;
+        for activity in trip.activities.iter() {
+            total_duration += activity.duration;
+        }
+
+        Ok(total_duration)
+    })
+}
+
+// budget is split in at most this many steps by the knapsack
+const SUGGESTION_COST_STEPS: usize = 1000;
+// activities considered per location, best scoring first
+const SUGGESTION_MAX_CANDIDATES: usize = 40;
+// longest trip activities are suggested for, in days
+const SUGGESTION_MAX_DAYS: i64 = 60;
+// free hours per location the knapsack table covers at most
+const SUGGESTION_MAX_HOURS: u32 = 240;
+
+// Suggest activities from the catalog of each destination that are not part of the
+// trip yet. Per location the chosen activities fit the free hours of the stay, and
+// together they fit the remaining budget, maximizing the preference score.
+#[ic_cdk::query]
+fn suggest_activities(trip_id: u64, preferences: SuggestionPreferences) -> Result<ActivitySuggestions,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let hours_per_day = preferences.hours_per_day.unwrap_or(8);
+    if !(1..=24).contains(&hours_per_day) {
+        return Err(Error::InvalidInput { msg: format!("hours_per_day must be between 1 and 24, got {}", hours_per_day) });
+    }
+    if parse_date(&trip.end_date)? - parse_date(&trip.start_date)? + 1 > SUGGESTION_MAX_DAYS {
+        return Err(Error::InvalidInput { msg: format!("Suggestions are limited to trips of at most {} days", SUGGESTION_MAX_DAYS) });
+    }
+    let remaining_budget = trip.budget - get_total_cost(trip_id)?;
+    let tag_weights = preferences
+        .tag_weights
+        .iter()
+        .map(|(tag, weight)| (tag.trim().to_lowercase(), *weight))
+        .collect::<Vec<_>>();
+    let score = |activity: &Activity| {
+        let category = preferences
+            .category_weights
+            .iter()
+            .find(|(category, _)| *category == activity.category)
+            .map_or(1.0, |(_, weight)| *weight);
+        let tags = tag_weights
+            .iter()
+            .filter(|(tag, _)| activity.tags.contains(tag))
+            .map(|(_, weight)| weight)
+            .sum::<f64>();
+        category + tags
+    };
+
+    // the cost unit keeps the budget dimension at SUGGESTION_COST_STEPS steps at most
+    let budget = remaining_budget.max(0.0);
+    let unit = (budget / SUGGESTION_COST_STEPS as f64).max(1.0);
+    let budget_steps = (budget / unit).floor() as usize;
+
+    // best score for every cost step, per location
+    let mut per_location = Vec::new();
+    for stop in trip.destinations.iter() {
+        let arrival = parse_date(&stop.arrival_date)?;
+        let departure = parse_date(&stop.departure_date)?;
+        let planned = trip
+            .activities
+            .iter()
+            .filter(|a| a.location == stop.location.id)
+            .fold(0u32, |hours, a| hours.saturating_add(a.duration));
+        let days = u32::try_from((departure - arrival).max(1)).unwrap_or(u32::MAX);
+        let free_hours = days
+            .saturating_mul(hours_per_day)
+            .saturating_sub(planned)
+            .min(SUGGESTION_MAX_HOURS);
+
+        let mut candidates = catalog_for_location(stop.location.id)
+            .into_iter()
+            .filter(|a| !trip.activities.iter().any(|planned| planned.id == a.id))
+            .filter(|a| (arrival..=departure).any(|day| check_activity_availability(a, day, None).is_ok()))
+            .map(|a| (score(&a), a))
+            .filter(|(score, a)| *score > 0.0 && a.duration <= free_hours && a.cost <= budget)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        candidates.truncate(SUGGESTION_MAX_CANDIDATES);
+
+        let best = knapsack_by_cost(&candidates, free_hours as usize, budget_steps, unit);
+        per_location.push((stop.location.id, free_hours, candidates, best));
+    }
+
+    // share the budget between locations: combined[c] is the best total score for cost <= c
+    let mut combined = vec![0.0; budget_steps + 1];
+    let mut spent = Vec::with_capacity(per_location.len());
+    for (_, _, _, best) in per_location.iter() {
+        let mut next = vec![0.0; budget_steps + 1];
+        let mut choice = vec![0usize; budget_steps + 1];
+        for c in 0..=budget_steps {
+            for k in 0..=c {
+                let total = combined[c - k] + best[k].0;
+                if total > next[c] {
+                    next[c] = total;
+                    choice[c] = k;
+                }
+            }
+        }
+        combined = next;
+        spent.push(choice);
+    }
+
+    let mut locations = Vec::new();
+    let mut c = budget_steps;
+    for (index, (location_id, free_hours, candidates, best)) in per_location.into_iter().enumerate().rev() {
+        let k = spent[index][c];
+        c -= k;
+        let activities = candidates
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| best[k].1 & (1 << i) != 0)
+            .map(|(_, (_, activity))| activity)
+            .collect::<Vec<_>>();
+        locations.push(LocationSuggestion {
+            location_id,
+            free_hours,
+            cost: activities.iter().map(|a| a.cost).sum(),
+            hours: activities.iter().map(|a| a.duration).sum(),
+            score: best[k].0,
+            activities,
+        });
+    }
+    locations.reverse();
+
+    Ok(ActivitySuggestions {
+        remaining_budget,
+        cost: locations.iter().map(|l| l.cost).sum(),
+        score: locations.iter().map(|l| l.score).sum(),
+        locations,
+    })
+}
+
+// 0/1 knapsack over hours and cost steps. Returns, for every cost step c, the best
+// score (and the chosen candidates as a bit mask) with cost <= c and hours <= `hours`.
+fn knapsack_by_cost(candidates: &[(f64, Activity)], hours: usize, cost_steps: usize, unit: f64) -> Vec<(f64, u64)> {
+    let width = cost_steps + 1;
+    let mut best = vec![(0.0, 0u64); (hours + 1) * width];
+    for (index, (score, activity)) in candidates.iter().enumerate() {
+        let duration = activity.duration as usize;
+        // rounding up keeps the suggestion within budget
+        let cost = (activity.cost / unit).ceil() as usize;
+        if duration > hours || cost > cost_steps {
+            continue;
+        }
+        for t in (duration..=hours).rev() {
+            for c in (cost..=cost_steps).rev() {
+                let (previous, mask) = best[(t - duration) * width + c - cost];
+                if previous + score > best[t * width + c].0 {
+                    best[t * width + c] = (previous + score, mask | (1 << index));
+                }
+            }
+        }
+    }
+    best.split_off(hours * width)
+}
+
+// activities offered at a location: the ones linked to it and the ones located there
+fn catalog_for_location(location_id: u64) -> Vec<Activity> {
+    let mut catalog = LOCATION_STR
+        .with(|m| m.borrow().get(&location_id))
+        .map(|l| l.activities)
+        .unwrap_or_default();
+    ACTIVITY_STR.with(|m| {
+        for (_, activity) in m.borrow().iter() {
+            if activity.location == location_id && !catalog.iter().any(|a| a.id == activity.id) {
+                catalog.push(activity);
+            }
+        }
+    });
+    catalog
+}
+
+// exhaustive search is used up to this many stops
+const ROUTE_EXACT_MAX_STOPS: usize = 12;
+
+// Propose the order of the trip destinations that minimizes total travel cost or
+// distance. Known transportations give the cost of a leg, the great-circle
+// distance between coordinates is used for distances and, priced at the average
+// cost per km of the known legs, to estimate missing costs.
+#[ic_cdk::query]
+fn optimize_destination_order(trip_id: u64, options: RouteOptions) -> Result<DestinationOrderProposal,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let stops = trip
+        .destinations
+        .iter()
+        .map(|stop| LOCATION_STR.with(|m| m.borrow().get(&stop.location.id)).unwrap_or_else(|| stop.location.clone()))
+        .collect::<Vec<_>>();
+    let n = stops.len();
+    if n < 2 {
+        return Err(Error::InvalidInput {
+            msg: format!("Trip id={} needs at least two destinations to be reordered", trip_id),
+        });
+    }
+
+    let transportations = TRANSPORTATION_STR.with(|m| m.borrow().iter().map(|(_, v)| v).collect::<Vec<_>>());
+    let known_cost = |a: &Location, b: &Location| {
+        transportations
+            .iter()
+            .filter(|t| (t.from == a.id && t.to == b.id) || (t.from == b.id && t.to == a.id))
+            .map(|t| t.cost)
+            .fold(None, |min: Option<f64>, cost| Some(min.map_or(cost, |min| min.min(cost))))
+    };
+
+    // average price of a km over the known legs between located places
+    let (priced_cost, priced_km) = transportations
+        .iter()
+        .filter_map(|t| {
+            let from = LOCATION_STR.with(|m| m.borrow().get(&t.from))?;
+            let to = LOCATION_STR.with(|m| m.borrow().get(&t.to))?;
+            distance_km(&from, &to).filter(|km| *km > 0.0).map(|km| (t.cost, km))
+        })
+        .fold((0.0, 0.0), |(cost, km), (c, k)| (cost + c, km + k));
+    let cost_per_km = if priced_km > 0.0 { Some(priced_cost / priced_km) } else { None };
+
+    // weights[i][j] = (value, estimated), None when unknown
+    let mut weights = vec![vec![None; n]; n];
+    for i in 0..n {
+        for j in 0..n {
+            if i == j {
+                continue;
+            }
+            weights[i][j] = match options.objective {
+                RouteObjective::Distance => distance_km(&stops[i], &stops[j]).map(|km| (km, true)),
+                RouteObjective::Cost => known_cost(&stops[i], &stops[j]).map(|cost| (cost, false)).or_else(|| {
+                    let km = distance_km(&stops[i], &stops[j])?;
+                    cost_per_km.map(|rate| (km * rate, true))
+                }),
+            };
+        }
+    }
+    let penalty = weights
+        .iter()
+        .flatten()
+        .filter_map(|w| w.map(|(value, _)| value))
+        .fold(0.0, f64::max)
+        .max(1.0)
+        * 10.0;
+    let cost = weights
+        .iter()
+        .map(|row| row.iter().map(|w| w.map_or(penalty, |(value, _)| value)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let start = if options.fixed_start { Some(0) } else { None };
+    let end = if options.fixed_end { Some(n - 1) } else { None };
+    let optimal = n <= ROUTE_EXACT_MAX_STOPS;
+    let order = if optimal {
+        shortest_path_exact(&cost, start, end)
+    } else {
+        shortest_path_two_opt(&cost, start.is_some(), end.is_some())
+    };
+
+    let path_total = |order: &[usize]| order.windows(2).map(|w| cost[w[0]][w[1]]).sum::<f64>();
+    let current_total = path_total(&(0..n).collect::<Vec<_>>());
+    let legs = order
+        .windows(2)
+        .map(|w| RouteLeg {
+            from: stops[w[0]].id,
+            to: stops[w[1]].id,
+            value: cost[w[0]][w[1]],
+            estimated: !matches!(weights[w[0]][w[1]], Some((_, false))),
+        })
+        .collect::<Vec<_>>();
+    let unknown_legs = order.windows(2).filter(|w| weights[w[0]][w[1]].is_none()).count() as u32;
+
+    Ok(DestinationOrderProposal {
+        objective: options.objective,
+        total: path_total(&order),
+        order: order.iter().map(|i| stops[*i].id).collect(),
+        legs,
+        current_total,
+        unknown_legs,
+        optimal,
+    })
+}
+
+// great-circle distance between two located places (haversine formula)
+fn distance_km(a: &Location, b: &Location) -> Option<f64> {
+    let (lat1, lon1) = (a.latitude?.to_radians(), a.longitude?.to_radians());
+    let (lat2, lon2) = (b.latitude?.to_radians(), b.longitude?.to_radians());
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    Some(2.0 * 6371.0 * h.sqrt().asin())
+}
+
+// Held-Karp dynamic program for the cheapest open path visiting every node once,
+// optionally starting and/or ending at a given node
+fn shortest_path_exact(cost: &[Vec<f64>], start: Option<usize>, end: Option<usize>) -> Vec<usize> {
+    let n = cost.len();
+    let full = (1usize << n) - 1;
+    let mut best = vec![vec![f64::INFINITY; n]; 1 << n];
+    let mut parent = vec![vec![usize::MAX; n]; 1 << n];
+    for j in 0..n {
+        let allowed = match start {
+            Some(start) => j == start,
+            None => Some(j) != end,
+        };
+        if allowed {
+            best[1 << j][j] = 0.0;
+        }
+    }
+
+    for mask in 1..=full {
+        for last in 0..n {
+            let current = best[mask][last];
+            if current == f64::INFINITY {
+                continue;
+            }
+            for next in 0..n {
+                if mask & (1 << next) != 0 {
+                    continue;
+                }
+                let next_mask = mask | (1 << next);
+                // the fixed end can only be visited last
+                if Some(next) == end && next_mask != full {
+                    continue;
+                }
+                let total = current + cost[last][next];
+                if total < best[next_mask][next] {
+                    best[next_mask][next] = total;
+                    parent[next_mask][next] = last;
+                }
+            }
+        }
+    }
+
+    let mut last = match end {
+        Some(end) => end,
+        None => (0..n)
+            .min_by(|a, b| best[full][*a].partial_cmp(&best[full][*b]).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or(0),
+    };
+    let mut mask = full;
+    let mut order = Vec::with_capacity(n);
+    while last != usize::MAX {
+        order.push(last);
+        let previous = parent[mask][last];
+        mask &= !(1 << last);
+        last = previous;
+    }
+    order.reverse();
+    order
+}
+
+// 2-opt improvement of the current order, for trips too large for the exact search
+fn shortest_path_two_opt(cost: &[Vec<f64>], fixed_start: bool, fixed_end: bool) -> Vec<usize> {
+    let n = cost.len();
+    let total = |order: &[usize]| order.windows(2).map(|w| cost[w[0]][w[1]]).sum::<f64>();
+    let mut order = (0..n).collect::<Vec<_>>();
+    let first = if fixed_start { 1 } else { 0 };
+    let last = if fixed_end { n - 1 } else { n };
+
+    let mut improved = true;
+    let mut passes = 0;
+    while improved && passes < 100 {
+        improved = false;
+        passes += 1;
+        for i in first..last {
+            for k in i + 1..last {
+                let mut candidate = order.clone();
+                candidate[i..=k].reverse();
+                if total(&candidate) + 1e-9 < total(&order) {
+                    order = candidate;
+                    improved = true;
+                }
+            }
+        }
+    }
+    order
+}
+
+// export a trip as an iCalendar (RFC 5545) document
+#[ic_cdk::query]
+fn export_trip_ics(trip_id: u64) -> Result<String,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    Ok(render_trip_ics(&trip))
+}
+
+// import locations from CSV text with the columns name, country, site, description,
+// latitude and longitude.
+// Rows are validated like `add_location`; if any row fails nothing is created.
+#[ic_cdk::update]
+fn import_locations_csv(text: String, options: CsvImportOptions) -> Result<LocationImportReport,Error> {
+    let rows = read_csv_records(
+        &text,
+        &options.columns,
+        &["name", "country"],
+        &["name", "country", "site", "description", "latitude", "longitude"],
+    )?;
+
+    let mut payloads = Vec::new();
+    let mut errors = Vec::new();
+    for (row, fields) in rows {
+        let coordinate = |value: &str| match value {
+            "" => Ok(None),
+            _ => value.parse::<f64>().map(Some).map_err(|_| format!("Invalid coordinate '{}'", value)),
+        };
+        let (latitude, longitude) = match (coordinate(&fields[4]), coordinate(&fields[5])) {
+            (Ok(latitude), Ok(longitude)) => (latitude, longitude),
+            (Err(msg), _) | (_, Err(msg)) => {
+                errors.push(CsvRowError { row, msg });
+                continue;
+            }
+        };
+        let payload = LocationPayload {
+            name: fields[0].clone(),
+            country: fields[1].clone(),
+            site: fields[2].clone(),
+            description: fields[3].clone(),
+            latitude,
+            longitude,
+        };
+        match payload.validate() {
+            Ok(()) => payloads.push(payload),
+            Err(err) => errors.push(CsvRowError { row, msg: error_message(&err) }),
+        }
+    }
+
+    let dry_run = options.dry_run || !errors.is_empty();
+    let locations = if dry_run {
+        let next_id = LOCATION_ID.with(|counter| *counter.borrow().get());
+        payloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| Location {
+                id: next_id + i as u64,
+                name: payload.name,
+                country: payload.country,
+                site: payload.site,
+                description: payload.description,
+                activities: Vec::new(),
+                latitude: payload.latitude,
+                longitude: payload.longitude,
+                version: 1,
+                created_at: ic_cdk::api::time(),
+                updated_at: ic_cdk::api::time(),
+                created_by: ic_cdk::caller(),
+                updated_by: ic_cdk::caller(),
+            })
+            .collect()
+    } else {
+        payloads
+            .into_iter()
+            .map(add_location)
+            .collect::<Result<Vec<_>,Error>>()?
+    };
+
+    Ok(LocationImportReport { dry_run, locations, errors })
+}
+
+// import activities from CSV text with the columns name, duration, cost, location, description,
+// category and tags (separated by ';').
+// Rows are validated like `add_activity`; if any row fails nothing is created.
+#[ic_cdk::update]
+fn import_activities_csv(text: String, options: CsvImportOptions) -> Result<ActivityImportReport,Error> {
+    let rows = read_csv_records(
+        &text,
+        &options.columns,
+        &["name", "duration", "cost", "location"],
+        &["name", "duration", "cost", "location", "description", "category", "tags"],
+    )?;
+
+    let mut payloads = Vec::new();
+    let mut errors = Vec::new();
+    for (row, fields) in rows {
+        let category = match parse_activity_category(&fields[5]) {
+            Some(category) => category,
+            None => {
+                errors.push(CsvRowError { row, msg: format!("Unknown category '{}'", fields[5]) });
+                continue;
+            }
+        };
+        let parsed = (
+            fields[1].trim().parse::<u32>(),
+            fields[2].trim().parse::<f64>(),
+            fields[3].trim().parse::<u64>(),
+        );
+        let payload = match parsed {
+            (Ok(duration), Ok(cost), Ok(location)) => ActivityPayload {
+                name: fields[0].clone(),
+                duration,
+                cost,
+                description: fields[4].clone(),
+                location,
+                category,
+                tags: fields[6]
+                    .split(';')
+                    .filter(|tag| !tag.trim().is_empty())
+                    .map(|tag| tag.to_string())
+                    .collect(),
+                opening_hours: Vec::new(),
+                season: None,
+            },
+            (Err(_), _, _) => {
+                errors.push(CsvRowError { row, msg: format!("Invalid duration '{}'", fields[1]) });
+                continue;
+            }
+            (_, Err(_), _) => {
+                errors.push(CsvRowError { row, msg: format!("Invalid cost '{}'", fields[2]) });
+                continue;
+            }
+            (_, _, Err(_)) => {
+                errors.push(CsvRowError { row, msg: format!("Invalid location id '{}'", fields[3]) });
+                continue;
+            }
+        };
+        match payload.validate() {
+            Ok(()) => payloads.push(payload),
+            Err(err) => errors.push(CsvRowError { row, msg: error_message(&err) }),
+        }
+    }
+
+    let dry_run = options.dry_run || !errors.is_empty();
+    let activities = if dry_run {
+        let next_id = ACTIVITY_ID.with(|counter| *counter.borrow().get());
+        payloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| Activity {
+                id: next_id + i as u64,
+                name: payload.name,
+                duration: payload.duration,
+                cost: payload.cost,
+                description: payload.description,
+                location: payload.location,
+                category: payload.category,
+                tags: normalize_tags(payload.tags),
+                opening_hours: payload.opening_hours,
+                season: payload.season,
+                version: 1,
+                created_at: ic_cdk::api::time(),
+                updated_at: ic_cdk::api::time(),
+                created_by: ic_cdk::caller(),
+                updated_by: ic_cdk::caller(),
+            })
+            .collect()
+    } else {
+        payloads
+            .into_iter()
+            .map(add_activity)
+            .collect::<Result<Vec<_>,Error>>()?
+    };
+
+    Ok(ActivityImportReport { dry_run, activities, errors })
+}
+
+// Parse CSV text and project every record onto `fields`, in that order.
+// Headers are matched to fields through `columns` first, then by their normalized
+// name ("Country Name" -> "country_name"). Returns (row number, values) pairs.
+fn read_csv_records(
+    text: &str,
+    columns: &[(String, String)],
+    required: &[&str],
+    fields: &[&str],
+) -> Result<Vec<(u64, Vec<String>)>,Error> {
+    let mut records = parse_csv(text)?.into_iter();
+    let (_, header) = records.next().ok_or(Error::InvalidInput {
+        msg: "CSV text is empty".to_string(),
+    })?;
+
+    let normalize = |name: &str| name.trim().to_lowercase().replace(' ', "_");
+    let positions = fields
+        .iter()
+        .map(|field| {
+            header.iter().position(|column| {
+                let mapped = columns
+                    .iter()
+                    .find(|(from, _)| normalize(from) == normalize(column))
+                    .map(|(_, to)| normalize(to));
+                mapped.unwrap_or_else(|| normalize(column)) == *field
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (field, position) in fields.iter().zip(positions.iter()) {
+        if position.is_none() && required.contains(field) {
+            return Err(Error::InvalidInput {
+                msg: format!("CSV header has no column for the required field '{}'", field),
+            });
+        }
+    }
+
+    Ok(records
+        .filter(|(_, values)| values.iter().any(|v| !v.trim().is_empty()))
+        .map(|(row, values)| {
+            let projected = positions
+                .iter()
+                .map(|position| {
+                    position
+                        .and_then(|i| values.get(i))
+                        .map(|v| v.trim().to_string())
+                        .unwrap_or_default()
+                })
+                .collect();
+            (row, projected)
+        })
+        .collect())
+}
+
+// RFC 4180 CSV parser: quoted fields, doubled quotes and CRLF or LF line endings.
+// Each record is returned with the line number it starts on.
+fn parse_csv(text: &str) -> Result<Vec<(u64, Vec<String>)>,Error> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut record)));
+                line += 1;
+                record_line = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(Error::InvalidInput {
+            msg: format!("Unterminated quoted field starting on line {}", record_line),
+        });
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    Ok(records)
+}
+
+// category by name, case-insensitive; an empty value is `Other`
+fn parse_activity_category(name: &str) -> Option<ActivityCategory> {
+    match name.trim().to_lowercase().as_str() {
+        "sightseeing" => Some(ActivityCategory::Sightseeing),
+        "museum" => Some(ActivityCategory::Museum),
+        "food" => Some(ActivityCategory::Food),
+        "nightlife" => Some(ActivityCategory::Nightlife),
+        "hiking" => Some(ActivityCategory::Hiking),
+        "beach" => Some(ActivityCategory::Beach),
+        "sport" => Some(ActivityCategory::Sport),
+        "shopping" => Some(ActivityCategory::Shopping),
+        "tour" => Some(ActivityCategory::Tour),
+        "other" | "" => Some(ActivityCategory::Other),
+        _ => None,
+    }
+}
+
+// keep the entities matching `options`, in the requested order
+fn apply_list_options<T: Tracked>(mut items: Vec<T>, options: ListOptions) -> Vec<T> {
+    let within = |time: u64, after: Option<u64>, before: Option<u64>| {
+        after.unwrap_or(0) <= time && time <= before.unwrap_or(u64::MAX)
+    };
+    items.retain(|item| {
+        let (created_at, created_by) = item.created();
+        let (updated_at, updated_by) = item.updated();
+        options.created_by.iter().all(|p| *p == created_by)
+            && options.updated_by.iter().all(|p| *p == updated_by)
+            && within(created_at, options.created_after, options.created_before)
+            && within(updated_at, options.updated_after, options.updated_before)
+    });
+    match options.sort_by.unwrap_or(SortField::Id) {
+        SortField::Id => items.sort_by_key(|item| item.id()),
+        SortField::CreatedAt => items.sort_by_key(|item| (item.created().0, item.id())),
+        SortField::UpdatedAt => items.sort_by_key(|item| (item.updated().0, item.id())),
+    }
+    if options.descending {
+        items.reverse();
+    }
+    items
+}
+
+// only the creator of a trip may manage its share tokens
+fn require_trip_owner(trip: &Trip) -> Result<(),Error> {
+    if ic_cdk::caller() == trip.created_by {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized {
+            msg: format!("Only the creator of trip id={} can do this", trip.id),
+        })
+    }
+}
+
+// only controllers of the canister may run admin endpoints
+fn require_controller() -> Result<(),Error> {
+    if ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized {
+            msg: "Only controllers of the canister can do this".to_string(),
+        })
+    }
+}
+
+// Fail with `Conflict` when the caller edited an older version of an entity than the
+// stored one. Updates without an expected version always apply.
+fn check_version(kind: &str, id: u64, current: u64, expected: Option<u64>) -> Result<(),Error> {
+    match expected {
+        Some(expected) if expected != current => Err(Error::Conflict {
+            msg: format!(
+                "{} with the id={} was changed by someone else: expected version {}, current version {}",
+                kind, id, expected, current
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn error_message(err: &Error) -> String {
+    match err {
+        Error::NotFound { msg }
+        | Error::InvalidInput { msg }
+        | Error::AlreadyExists { msg }
+        | Error::Conflict { msg }
+        | Error::Unauthorized { msg } => msg.clone(),
+    }
+}
+
+const MAX_BATCH_OPERATIONS: usize = 200;
+
+// Apply a list of operations atomically. References are checked before anything is
+// written; if an operation fails afterwards the call traps, which makes the IC roll
+// back every change made by the earlier operations of the batch.
+#[ic_cdk::update]
+fn apply_batch(operations: Vec<Operation>) -> Result<Vec<BatchResult>,Error> {
+    if operations.is_empty() {
+        return Err(Error::InvalidInput { msg: "Batch is empty".to_string() });
+    }
+    if operations.len() > MAX_BATCH_OPERATIONS {
+        return Err(Error::InvalidInput {
+            msg: format!("Batch has more than {} operations", MAX_BATCH_OPERATIONS),
+        });
+    }
+
+    let kinds = operations.iter().map(operation_kind).collect::<Vec<_>>();
+    for (index, operation) in operations.iter().enumerate() {
+        for (reference, kind) in operation_refs(operation) {
+            if let IdRef::Op(op) = reference {
+                let op = op as usize;
+                if op >= index || kinds[op] != kind {
+                    return Err(Error::InvalidInput {
+                        msg: format!(
+                            "Operation #{} references operation #{} which does not produce a {:?}",
+                            index, op, kind
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    let mut results: Vec<BatchResult> = Vec::with_capacity(operations.len());
+    for (index, operation) in operations.into_iter().enumerate() {
+        let kind = kinds[index];
+        match apply_operation(operation, &results) {
+            Ok(id) => results.push(BatchResult { kind, id }),
+            Err(err) => ic_cdk::trap(&format!(
+                "Batch operation #{} failed, no changes were applied: {}",
+                index,
+                error_message(&err)
+            )),
+        }
+    }
+    Ok(results)
+}
+
+fn operation_kind(operation: &Operation) -> EntityKind {
+    match operation {
+        Operation::AddLocation(_)
+        | Operation::UpdateLocation { .. }
+        | Operation::AddActivityToLocation { .. } => EntityKind::Location,
+        Operation::AddActivity { .. } | Operation::UpdateActivity { .. } => EntityKind::Activity,
+        Operation::AddTrip(_)
+        | Operation::UpdateTrip { .. }
+        | Operation::AddDestinationToTrip { .. }
+        | Operation::AddActivityToTrip { .. }
+        | Operation::AddTransportationToTrip { .. }
+        | Operation::ScheduleActivity { .. } => EntityKind::Trip,
+        Operation::AddTransportation { .. } | Operation::UpdateTransportation { .. } => {
+            EntityKind::Transportation
+        }
+    }
+}
+
+// every reference of an operation together with the kind of entity it must point to
+fn operation_refs(operation: &Operation) -> Vec<(IdRef, EntityKind)> {
+    let mut refs = Vec::new();
+    let mut optional = |reference: &Option<IdRef>, kind: EntityKind| {
+        if let Some(reference) = reference {
+            refs.push((*reference, kind));
+        }
+    };
+    match operation {
+        Operation::AddLocation(_) | Operation::AddTrip(_) => {}
+        Operation::AddActivity { location, .. } => optional(location, EntityKind::Location),
+        Operation::AddTransportation { from, to, .. } => {
+            optional(from, EntityKind::Location);
+            optional(to, EntityKind::Location);
+        }
+        Operation::ScheduleActivity { trip, activity, .. } => {
+            optional(activity, EntityKind::Activity);
+            refs.push((*trip, EntityKind::Trip));
+        }
+        Operation::UpdateActivity { id, location, .. } => {
+            optional(location, EntityKind::Location);
+            refs.push((*id, EntityKind::Activity));
+        }
+        Operation::UpdateTransportation { id, from, to, .. } => {
+            optional(from, EntityKind::Location);
+            optional(to, EntityKind::Location);
+            refs.push((*id, EntityKind::Transportation));
+        }
+        Operation::UpdateLocation { id, .. } => refs.push((*id, EntityKind::Location)),
+        Operation::UpdateTrip { id, .. } => refs.push((*id, EntityKind::Trip)),
+        Operation::AddActivityToLocation { location, activity } => {
+            refs.push((*location, EntityKind::Location));
+            refs.push((*activity, EntityKind::Activity));
+        }
+        Operation::AddDestinationToTrip { trip, location, .. } => {
+            optional(location, EntityKind::Location);
+            refs.push((*trip, EntityKind::Trip));
+        }
+        Operation::AddActivityToTrip { trip, activity } => {
+            refs.push((*trip, EntityKind::Trip));
+            refs.push((*activity, EntityKind::Activity));
+        }
+        Operation::AddTransportationToTrip { trip, transportation } => {
+            refs.push((*trip, EntityKind::Trip));
+            refs.push((*transportation, EntityKind::Transportation));
+        }
+    }
+    refs
+}
+
+// run one batch operation and return the id of the entity it produced
+fn apply_operation(operation: Operation, results: &[BatchResult]) -> Result<u64,Error> {
+    // references were validated by `apply_batch`
+    let resolve = |reference: IdRef| match reference {
+        IdRef::Id(id) => id,
+        IdRef::Op(op) => results[op as usize].id,
+    };
+    match operation {
+        Operation::AddLocation(payload) => add_location(payload).map(|l| l.id),
+        Operation::UpdateLocation { id, payload, expected_version } => {
+            update_location(resolve(id), payload, expected_version).map(|l| l.id)
+        }
+        Operation::AddActivity { mut payload, location } => {
+            if let Some(location) = location {
+                payload.location = resolve(location);
+            }
+            add_activity(payload).map(|a| a.id)
+        }
+        Operation::UpdateActivity { id, mut payload, location, expected_version } => {
+            if let Some(location) = location {
+                payload.location = resolve(location);
+            }
+            update_activity(resolve(id), payload, expected_version).map(|a| a.id)
+        }
+        Operation::AddTrip(payload) => add_trip(payload).map(|t| t.id),
+        Operation::UpdateTrip { id, payload, expected_version } => {
+            update_trip(resolve(id), payload, expected_version).map(|t| t.id)
+        }
+        Operation::AddTransportation { mut payload, from, to } => {
+            if let Some(from) = from {
+                payload.from = resolve(from);
+            }
+            if let Some(to) = to {
+                payload.to = resolve(to);
+            }
+            add_transportation(payload).map(|t| t.id)
+        }
+        Operation::UpdateTransportation { id, mut payload, from, to, expected_version } => {
+            if let Some(from) = from {
+                payload.from = resolve(from);
+            }
+            if let Some(to) = to {
+                payload.to = resolve(to);
+            }
+            update_transportation(resolve(id), payload, expected_version).map(|t| t.id)
+        }
+        Operation::AddActivityToLocation { location, activity } => {
+            let location = resolve(location);
+            add_activity_to_location(location, resolve(activity)).map(|_| location)
+        }
+        Operation::AddDestinationToTrip { trip, mut payload, location } => {
+            if let Some(location) = location {
+                payload.location_id = resolve(location);
+            }
+            let trip = resolve(trip);
+            add_destination_to_trip(trip, payload).map(|_| trip)
+        }
+        Operation::AddActivityToTrip { trip, activity } => {
+            let trip = resolve(trip);
+            add_activity_to_trip(trip, resolve(activity)).map(|_| trip)
+        }
+        Operation::AddTransportationToTrip { trip, transportation } => {
+            let trip = resolve(trip);
+            add_transportation_to_trip(trip, resolve(transportation)).map(|_| trip)
+        }
+        Operation::ScheduleActivity { trip, mut payload, activity } => {
+            if let Some(activity) = activity {
+                payload.activity_id = resolve(activity);
+            }
+            schedule_activity(resolve(trip), payload).map(|t| t.id)
+        }
+    }
+}
+
+// export a trip as a GeoJSON FeatureCollection: a Point per destination and per
+// activity location, a LineString per transportation leg. Locations without
+// coordinates are left out.
+#[ic_cdk::query]
+fn export_trip_geojson(trip_id: u64) -> Result<String,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let location = |id: u64| LOCATION_STR.with(|m| m.borrow().get(&id));
+    let mut features = Vec::new();
+
+    for stop in trip.destinations.iter() {
+        // the stored copy may predate the coordinates, prefer the current record
+        let current = location(stop.location.id).unwrap_or_else(|| stop.location.clone());
+        if let Some(point) = geojson_point(&current) {
+            features.push(serde_json::json!({
+                "type": "Feature",
+                "geometry": point,
+                "properties": {
+                    "kind": "destination",
+                    "location_id": current.id,
+                    "name": current.name,
+                    "country": current.country,
+                    "sequence": stop.sequence,
+                    "arrival_date": stop.arrival_date,
+                    "departure_date": stop.departure_date,
+                },
+            }));
+        }
+    }
+
+    for activity in trip.activities.iter() {
+        let point = match location(activity.location).as_ref().and_then(geojson_point) {
+            Some(point) => point,
+            None => continue,
+        };
+        let date = trip
+            .schedule
+            .iter()
+            .find(|slot| slot.activity_id == activity.id)
+            .map(|slot| slot.date.clone());
+        features.push(serde_json::json!({
+            "type": "Feature",
+            "geometry": point,
+            "properties": {
+                "kind": "activity",
+                "activity_id": activity.id,
+                "location_id": activity.location,
+                "name": activity.name,
+                "cost": activity.cost,
+                "duration": activity.duration,
+                "date": date,
+            },
+        }));
+    }
+
+    for leg in trip.transportation.iter() {
+        let ends = (location(leg.from), location(leg.to));
+        let coordinates = match ends {
+            (Some(from), Some(to)) => match (geojson_position(&from), geojson_position(&to)) {
+                (Some(from), Some(to)) => vec![from, to],
+                _ => continue,
+            },
+            _ => continue,
+        };
+        features.push(serde_json::json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": coordinates,
+            },
+            "properties": {
+                "kind": "transportation",
+                "transportation_id": leg.id,
+                "from": leg.from,
+                "to": leg.to,
+                "mode": leg.type_,
+                "cost": leg.cost,
+                "date": leg.date,
+            },
+        }));
+    }
+
+    let collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    Ok(collection.to_string())
+}
+
+// GeoJSON positions are [longitude, latitude]
+fn geojson_position(location: &Location) -> Option<[f64; 2]> {
+    match (location.longitude, location.latitude) {
+        (Some(longitude), Some(latitude)) => Some([longitude, latitude]),
+        _ => None,
+    }
+}
+
+fn geojson_point(location: &Location) -> Option<serde_json::Value> {
+    geojson_position(location).map(|position| {
+        serde_json::json!({
+            "type": "Point",
+            "coordinates": position,
+        })
+    })
+}
+
+// Copy a trip to start on `new_start_date`. Stops, schedule, transportations and
+// accommodations move by the same number of days; transportations and
+// accommodations are copied to new records, activities are shared.
+#[ic_cdk::update]
+fn clone_trip(trip_id: u64, new_start_date: String) -> Result<Trip,Error> {
+    let source = get_trip_by_id(trip_id)?;
+    let offset = parse_date(&new_start_date)? - parse_date(&source.start_date)?;
+
+    // shift every date before creating anything, so bad dates leave no copies behind
+    let mut trip = source.clone();
+    trip.start_date = shift_date(&source.start_date, offset)?;
+    trip.end_date = shift_date(&source.end_date, offset)?;
+    for stop in trip.destinations.iter_mut() {
+        stop.arrival_date = shift_date(&stop.arrival_date, offset)?;
+        stop.departure_date = shift_date(&stop.departure_date, offset)?;
+    }
+    for slot in trip.schedule.iter_mut() {
+        slot.date = shift_date(&slot.date, offset)?;
+    }
+    for leg in trip.transportation.iter_mut() {
+        leg.date = shift_date(&leg.date, offset)?;
+    }
+    for accommodation in trip.accommodations.iter_mut() {
+        accommodation.check_in = shift_date(&accommodation.check_in, offset)?;
+        accommodation.check_out = shift_date(&accommodation.check_out, offset)?;
+    }
+
+    trip.id = TRIP_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    for leg in trip.transportation.iter_mut() {
+        leg.id = TRANSPORTATION_ID
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment id counter");
+        leg.version = 0;
+        leg.created_at = ic_cdk::api::time();
+        leg.created_by = ic_cdk::caller();
+        leg.touch();
+        TRANSPORTATION_STR.with(|m| m.borrow_mut().insert(leg.id, leg.clone()));
+        record_audit("clone_trip", EntityKind::Transportation, leg.id, None, Some(&leg));
+    }
+    for accommodation in trip.accommodations.iter_mut() {
+        let old_id = accommodation.id;
+        accommodation.id = ACCOMMODATION_ID
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment id counter");
+        accommodation.version = 0;
+        accommodation.created_at = ic_cdk::api::time();
+        accommodation.created_by = ic_cdk::caller();
+        accommodation.touch();
+        for stop in trip.destinations.iter_mut() {
+            if stop.accommodation == Some(old_id) {
+                stop.accommodation = Some(accommodation.id);
+            }
+        }
+        ACCOMMODATION_STR.with(|m| m.borrow_mut().insert(accommodation.id, accommodation.clone()));
+        record_audit("clone_trip", EntityKind::Accommodation, accommodation.id, None, Some(&accommodation));
+    }
+
+    trip.version = 0;
+    trip.status = TripStatus::Draft;
+    trip.bookings = Vec::new();
+    trip.created_at = ic_cdk::api::time();
+    trip.created_by = ic_cdk::caller();
+    record_trip_version("clone_trip", &mut trip)?;
+    TRIP_STR.with(|m| m.borrow_mut().insert(trip.id, trip.clone()));
+    record_audit("clone_trip", EntityKind::Trip, trip.id, None, Some(&trip));
+    if let Some(mut list) = PACKING_STR.with(|m| m.borrow().get(&trip_id)) {
+        list.trip_id = trip.id;
+        for item in list.items.iter_mut() {
+            item.packed = false;
+        }
+        PACKING_STR.with(|m| m.borrow_mut().insert(trip.id, list));
+    }
+    // due dates are relative to the start date, so the checklist moves along with the trip
+    if let Some(mut checklist) = CHECKLIST_STR.with(|m| m.borrow().get(&trip_id)) {
+        checklist.trip_id = trip.id;
+        for task in checklist.tasks.iter_mut() {
+            task.done = false;
+            task.completed_at = None;
+            task.completed_by = None;
+        }
+        CHECKLIST_STR.with(|m| m.borrow_mut().insert(trip.id, checklist));
+    }
+    Ok(trip)
+}
+
+// function to save the skeleton of a trip as a template
+#[ic_cdk::update]
+fn save_as_template(trip_id: u64, name: String) -> Result<TripTemplate,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    if name.trim().is_empty() {
+        return Err(Error::InvalidInput { msg: "Template name is required".to_string() });
+    }
+    let start = parse_date(&trip.start_date)?;
+    let day_of = |date: &str| -> Result<u32,Error> { Ok((parse_date(date)? - start).max(0) as u32) };
+
+    let mut stops = Vec::new();
+    for stop in trip.destinations.iter() {
+        let arrival_day = day_of(&stop.arrival_date)?;
+        stops.push(TemplateStop {
+            location_id: stop.location.id,
+            arrival_day,
+            nights: day_of(&stop.departure_date)?.saturating_sub(arrival_day),
+        });
+    }
+    let mut schedule = Vec::new();
+    for slot in trip.schedule.iter() {
+        schedule.push(TemplateSlot {
+            activity_id: slot.activity_id,
+            day: day_of(&slot.date)?,
+            start_time: slot.start_time.clone(),
+        });
+    }
+    let mut transportation = Vec::new();
+    for leg in trip.transportation.iter() {
+        let (day, minutes) = parse_date_time(&leg.date)?;
+        transportation.push(TemplateLeg {
+            type_: leg.type_.clone(),
+            from: leg.from,
+            to: leg.to,
+            day: (day - start).max(0) as u32,
+            time: minutes.map(|m| format!("{:02}:{:02}", m / 60, m % 60)),
+        });
+    }
+
+    let id = TEMPLATE_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    let template = TripTemplate {
+        id,
+        name,
+        days: day_of(&trip.end_date)?,
+        stops,
+        activities: trip.activities.iter().map(|a| a.id).collect(),
+        schedule,
+        transportation,
+    };
+
+    TEMPLATE_STR.with(|m| m.borrow_mut().insert(id, template.clone()));
+    record_audit("save_as_template", EntityKind::Template, id, None, Some(&template));
+    Ok(template)
+}
+
+// function to get all templates
+#[ic_cdk::query]
+fn get_templates() -> Result<Vec<TripTemplate>,Error> {
 
-    LOCATION_STR.with(|m| m.borrow_mut().insert(id, location.clone()));
-    Ok(location)
+    let templates = TEMPLATE_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
+    if templates.is_empty() {
+        return Err(Error::NotFound { msg: "No Templates  found".to_string() });
+    }
+    Ok(templates)
 }
 
-// function to update a location
-#[ic_cdk::update]
-fn update_location(id: u64, payload: LocationPayload) -> Result<Location,Error> {
-    LOCATION_STR.with(|m| {
-        let mut location = m
-            .borrow_mut()
+// function to get template by id
+#[ic_cdk::query]
+fn get_template_by_id(id: u64) -> Result<TripTemplate,Error> {
+    TEMPLATE_STR.with(|service| {
+        service
+            .borrow()
             .get(&id)
             .ok_or(Error::NotFound {
-                msg: format!("Location with the id={} not found", id),
-            })?;
-
-        location.name = payload.name;
-        location.country = payload.country;
-        location.site = payload.site;
-        location.description = payload.description;
-
-        m.borrow_mut().insert(id, location.clone());
-        Ok(location)
+                msg: format!("Template with the  id={} not found", id),
+            })
     })
 }
 
-// function to delete a location
+// function to delete a template
 #[ic_cdk::update]
-fn delete_location(id: u64) -> Result<(),Error> {
-    LOCATION_STR.with(|m| {
+fn delete_template(id: u64) -> Result<(),Error> {
+    TEMPLATE_STR.with(|m| {
         m.borrow_mut()
             .remove(&id)
             .ok_or(Error::NotFound {
-                msg: format!("Location with the id={} not found", id),
+                msg: format!("Template with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_template", EntityKind::Template, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::Template(before));
             })
-            .map(|_| ())
     })
 }
 
-// function to get all activities
-#[ic_cdk::query]
-fn get_activities() -> Result<Vec<Activity>,Error> {
+// Create a trip from a template starting on `payload.start_date`. Transportations are
+// created without cost. Like `apply_batch`, a failure after the checks traps so that
+// no partial trip is kept.
+#[ic_cdk::update]
+fn instantiate_template(template_id: u64, payload: TemplateInstancePayload) -> Result<Trip,Error> {
+    let template = get_template_by_id(template_id)?;
+    let start = parse_date(&payload.start_date)?;
+    for location_id in template
+        .stops
+        .iter()
+        .map(|s| s.location_id)
+        .chain(template.transportation.iter().flat_map(|t| [t.from, t.to]))
+    {
+        get_location_by_id(location_id)?;
+    }
+    for activity_id in template.activities.iter() {
+        get_activity_by_id(*activity_id)?;
+    }
 
-    let activities = ACTIVITY_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
-    if activities.len() == 0 {
-        return Err(Error::NotFound { msg: "No Activities  found".to_string() });
+    let day = |offset: u32| format_date(start + offset as i64);
+    let trip = add_trip(TripPayload {
+        name: payload.name,
+        start_date: day(0),
+        end_date: day(template.days),
+        budget: payload.budget,
+    })?;
+
+    let built = (|| -> Result<Trip,Error> {
+        for stop in template.stops.iter() {
+            add_destination_to_trip(
+                trip.id,
+                StopPayload {
+                    location_id: stop.location_id,
+                    arrival_date: day(stop.arrival_day),
+                    departure_date: day(stop.arrival_day + stop.nights),
+                    accommodation: None,
+                },
+            )?;
+        }
+        for activity_id in template.activities.iter() {
+            add_activity_to_trip(trip.id, *activity_id)?;
+        }
+        for slot in template.schedule.iter() {
+            schedule_activity(
+                trip.id,
+                SchedulePayload {
+                    activity_id: slot.activity_id,
+                    date: day(slot.day),
+                    start_time: slot.start_time.clone(),
+                },
+            )?;
+        }
+        for leg in template.transportation.iter() {
+            let date = match leg.time.as_ref() {
+                Some(time) => format!("{}T{}", day(leg.day), time),
+                None => day(leg.day),
+            };
+            let transportation = add_transportation(TransportationPayload {
+                type_: leg.type_.clone(),
+                from: leg.from,
+                to: leg.to,
+                cost: 0.0,
+                date,
+            })?;
+            add_transportation_to_trip(trip.id, transportation.id)?;
+        }
+        get_trip_by_id(trip.id)
+    })();
+
+    match built {
+        Ok(trip) => Ok(trip),
+        Err(err) => ic_cdk::trap(&format!(
+            "Cannot instantiate template id={}, no changes were applied: {}",
+            template_id,
+            error_message(&err)
+        )),
     }
-    Ok(activities)
 }
 
-// function to get activity by id
+// move a "YYYY-MM-DD" date, with an optional time part, by a number of days
+fn shift_date(value: &str, days: i64) -> Result<String,Error> {
+    let (day, minutes) = parse_date_time(value)?;
+    let date = format_date(day + days);
+    Ok(match minutes {
+        Some(minutes) => format!("{}T{:02}:{:02}", date, minutes / 60, minutes % 60),
+        None => date,
+    })
+}
+
+// every stored version reserves the full `TripVersion::MAX_SIZE`
+const TRIP_MAX_VERSIONS: u64 = 10;
+
+// function to list the kept versions of a trip, oldest first
 #[ic_cdk::query]
-fn get_activity_by_id(id: u64) -> Result<Activity,Error> {
-    ACTIVITY_STR.with(|service| {
-        service
-            .borrow_mut()
-            .get(&id)
+fn get_trip_versions(trip_id: u64) -> Result<Vec<TripVersionInfo>,Error> {
+    get_trip_by_id(trip_id)?;
+    Ok(TRIP_VERSION_STR.with(|m| {
+        m.borrow()
+            .range((trip_id, 0)..=(trip_id, u64::MAX))
+            .map(|((_, version), v)| TripVersionInfo {
+                version,
+                endpoint: v.endpoint,
+                caller: v.caller,
+                timestamp: v.timestamp,
+            })
+            .collect()
+    }))
+}
+
+// function to get a trip as it was at a given version
+#[ic_cdk::query]
+fn get_trip_version(trip_id: u64, version: u64) -> Result<Trip,Error> {
+    TRIP_VERSION_STR.with(|m| {
+        m.borrow()
+            .get(&(trip_id, version))
+            .map(|v| v.trip)
             .ok_or(Error::NotFound {
-                msg: format!("Activity with the  id={} not found", id),
+                msg: format!("Version {} of trip id={} not found", version, trip_id),
             })
     })
 }
 
-// function to add an activity
+// function to bring a trip back to a past version, the restore is itself a new version
 #[ic_cdk::update]
-fn add_activity(payload: ActivityPayload) -> Result<Activity,Error> {
+fn restore_trip_version(trip_id: u64, version: u64) -> Result<Trip,Error> {
+    let current = get_trip_by_id(trip_id)?;
+    check_trip_editable(&current)?;
+    let mut trip = get_trip_version(trip_id, version)?;
+    // the status is not part of what a restore brings back
+    trip.status = current.status;
+    trip.version = current.version;
+    record_trip_version("restore_trip_version", &mut trip)?;
+    TRIP_STR.with(|m| m.borrow_mut().insert(trip_id, trip.clone()));
+    record_audit("restore_trip_version", EntityKind::Trip, trip_id, Some(&current), Some(&trip));
+    Ok(trip)
+}
 
-    // validate user payload all fields are required
+// function to list what changed in a trip between two versions
+#[ic_cdk::query]
+fn diff_trip_versions(trip_id: u64, from: u64, to: u64) -> Result<Vec<TripChange>,Error> {
+    let before = get_trip_version(trip_id, from)?;
+    let after = get_trip_version(trip_id, to)?;
 
-    let id = ACTIVITY_ID
+    let mut changes = Vec::new();
+    diff_value(&mut changes, "name", None, &before.name, &after.name);
+    diff_value(&mut changes, "start_date", None, &before.start_date, &after.start_date);
+    diff_value(&mut changes, "end_date", None, &before.end_date, &after.end_date);
+    diff_value(&mut changes, "budget", None, &before.budget, &after.budget);
+    diff_items(&mut changes, "destinations", &before.destinations, &after.destinations, |s| s.location.id);
+    diff_items(&mut changes, "activities", &before.activities, &after.activities, |a| a.id);
+    diff_items(&mut changes, "schedule", &before.schedule, &after.schedule, |s| s.activity_id);
+    diff_items(&mut changes, "transportation", &before.transportation, &after.transportation, |t| t.id);
+    diff_items(&mut changes, "accommodations", &before.accommodations, &after.accommodations, |a| a.id);
+    Ok(changes)
+}
+
+fn diff_value<T: serde::Serialize>(changes: &mut Vec<TripChange>, field: &str, item: Option<u64>, before: &T, after: &T) {
+    let before = serde_json::to_string(before).unwrap_or_default();
+    let after = serde_json::to_string(after).unwrap_or_default();
+    if before != after {
+        changes.push(TripChange { field: field.to_string(), item, before: Some(before), after: Some(after) });
+    }
+}
+
+// changes of a list whose items are identified by `key`, in the order of `after`
+// followed by the removed items
+fn diff_items<T: serde::Serialize>(changes: &mut Vec<TripChange>, field: &str, before: &[T], after: &[T], key: impl Fn(&T) -> u64) {
+    for item in after {
+        match before.iter().find(|b| key(b) == key(item)) {
+            Some(old) => diff_value(changes, field, Some(key(item)), old, item),
+            None => changes.push(TripChange {
+                field: field.to_string(),
+                item: Some(key(item)),
+                before: None,
+                after: Some(serde_json::to_string(item).unwrap_or_default()),
+            }),
+        }
+    }
+    for item in before {
+        if !after.iter().any(|a| key(a) == key(item)) {
+            changes.push(TripChange {
+                field: field.to_string(),
+                item: Some(key(item)),
+                before: Some(serde_json::to_string(item).unwrap_or_default()),
+                after: None,
+            });
+        }
+    }
+}
+
+// Bump the version of a trip that is about to be written and keep a copy of it.
+// Only the last `TRIP_MAX_VERSIONS` versions of each trip are kept. As every write
+// of a trip goes through here, this is also where its status timer is re-armed.
+fn record_trip_version(endpoint: &str, trip: &mut Trip) -> Result<(),Error> {
+    trip.touch();
+    check_trip_size(trip)?;
+    arm_status_timer(trip);
+    let version = TripVersion {
+        trip: trip.clone(),
+        endpoint: endpoint.to_string(),
+        caller: ic_cdk::caller(),
+        timestamp: ic_cdk::api::time(),
+    };
+    TRIP_VERSION_STR.with(|m| {
+        let mut versions = m.borrow_mut();
+        versions.insert((trip.id, trip.version), version);
+        if trip.version > TRIP_MAX_VERSIONS {
+            versions.remove(&(trip.id, trip.version - TRIP_MAX_VERSIONS));
+        }
+    });
+    Ok(())
+}
+
+// reject a change that would make the trip too large to be stored
+fn check_trip_size(trip: &Trip) -> Result<(),Error> {
+    check_record_size("Trip", trip.id, trip)
+}
+
+// reject a record that would not fit the entries of its stable map
+fn check_record_size<T: candid::CandidType + serde::Serialize + BoundedStorable>(entity: &str, id: u64, record: &T) -> Result<(),Error> {
+    let size = Encode!(record).map(|bytes| bytes.len()).unwrap_or(usize::MAX);
+    if size > T::MAX_SIZE as usize {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "{} with the id={} would take {} bytes, at most {} are allowed",
+                entity,
+                id,
+                size,
+                T::MAX_SIZE
+            ),
+        });
+    }
+    Ok(())
+}
+
+// every stored entry reserves the full `AuditEntry::MAX_SIZE`, keep both small
+const AUDIT_MAX_ENTRIES: u64 = 10_000;
+const AUDIT_MAX_SNAPSHOT_SIZE: usize = 1024;
+const AUDIT_PAGE_SIZE: u32 = 50;
+const AUDIT_MAX_PAGE_SIZE: u32 = 500;
+
+// function to get a page of the audit log, optionally for one kind of entity or one entity
+#[ic_cdk::query]
+fn get_audit_log(query: AuditQuery) -> Result<AuditPage,Error> {
+    let limit = query.limit.unwrap_or(AUDIT_PAGE_SIZE);
+    if limit == 0 || limit > AUDIT_MAX_PAGE_SIZE {
+        return Err(Error::InvalidInput {
+            msg: format!("Page size must be between 1 and {}", AUDIT_MAX_PAGE_SIZE),
+        });
+    }
+    let start = match query.after {
+        Some(after) => after.saturating_add(1),
+        None => 0,
+    };
+
+    let mut entries = Vec::new();
+    let mut next = None;
+    AUDIT_STR.with(|m| {
+        for (_, entry) in m.borrow().range(start..) {
+            if query.entity.is_some_and(|kind| kind != entry.entity)
+                || query.entity_id.is_some_and(|entity_id| entity_id != entry.entity_id)
+            {
+                continue;
+            }
+            if entries.len() == limit as usize {
+                next = entries.last().map(|e: &AuditEntry| e.id);
+                break;
+            }
+            entries.push(entry);
+        }
+    });
+    Ok(AuditPage { entries, next })
+}
+
+// Append an entry to the audit log. For updates only the top-level fields that changed
+// are kept on both sides. Only the last `AUDIT_MAX_ENTRIES` entries are kept, and a
+// side longer than `AUDIT_MAX_SNAPSHOT_SIZE` is left out and flagged instead of cut.
+fn record_audit<T: serde::Serialize>(endpoint: &str, entity: EntityKind, entity_id: u64, before: Option<&T>, after: Option<&T>) {
+    let id = AUDIT_ID
     .with(|counter| {
         let current_value = *counter.borrow().get();
         counter.borrow_mut().set(current_value + 1)
     })
     .expect("cannot increment id counter");
 
-    let activity = Activity {
+    // keep what changed only
+    let mut before = before.and_then(|value| serde_json::to_value(value).ok());
+    let mut after = after.and_then(|value| serde_json::to_value(value).ok());
+    if let (Some(serde_json::Value::Object(old)), Some(serde_json::Value::Object(new))) = (before.as_mut(), after.as_mut()) {
+        let unchanged = old
+            .iter()
+            .filter(|(field, value)| new.get(*field) == Some(*value))
+            .map(|(field, _)| field.clone())
+            .collect::<Vec<_>>();
+        for field in unchanged {
+            old.remove(&field);
+            new.remove(&field);
+        }
+    }
+
+    let before = before.map(|value| value.to_string());
+    let after = after.map(|value| value.to_string());
+    let fits = |json: &String| json.len() <= AUDIT_MAX_SNAPSHOT_SIZE;
+    let entry = AuditEntry {
         id,
-        name: payload.name,
-        duration: payload.duration,
-        cost: payload.cost,
-        description: payload.description,
-        location: payload.location,
+        caller: ic_cdk::caller(),
+        timestamp: ic_cdk::api::time(),
+        endpoint: endpoint.to_string(),
+        entity,
+        entity_id,
+        snapshot_omitted: !before.iter().chain(after.iter()).all(fits),
+        before: before.filter(fits),
+        after: after.filter(fits),
     };
 
-    ACTIVITY_STR.with(|m| m.borrow_mut().insert(id, activity.clone()));
-    Ok(activity)
+    AUDIT_STR.with(|m| {
+        let mut log = m.borrow_mut();
+        log.insert(id, entry);
+        if id >= AUDIT_MAX_ENTRIES {
+            log.remove(&(id - AUDIT_MAX_ENTRIES));
+        }
+    });
 }
 
-// function to update an activity
+const TRASH_DEFAULT_RETENTION_DAYS: u64 = 30;
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[ic_cdk::init]
+fn init() {
+    STORAGE_VERSION_CELL
+        .with(|cell| cell.borrow_mut().set(STORAGE_VERSION))
+        .expect("cannot set the storage version");
+    start_timers();
+}
+
+// timers do not survive upgrades and are started again
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    migrate_storage();
+    start_timers();
+}
+
+// Bring stable memory written by an older release up to `STORAGE_VERSION`. Each step
+// runs once and records the version it reached.
+fn migrate_storage() {
+    let version = STORAGE_VERSION_CELL.with(|cell| *cell.borrow().get());
+    if version < 1 {
+        migrate_first_release();
+    }
+    STORAGE_VERSION_CELL
+        .with(|cell| cell.borrow_mut().set(STORAGE_VERSION))
+        .expect("cannot set the storage version");
+}
+
+// Version 1: move the records of the first release into the current maps. They get
+// the upgrading controller as their author, trips become planned and their stops
+// split the trip dates in turn, sharing their travel days.
+fn migrate_first_release() {
+    let now = ic_cdk::api::time();
+    let caller = ic_cdk::caller();
+    let activity = |legacy: LegacyActivity| Activity {
+        id: legacy.id,
+        name: legacy.name,
+        duration: legacy.duration,
+        cost: legacy.cost,
+        description: legacy.description,
+        location: legacy.location,
+        category: ActivityCategory::Other,
+        tags: Vec::new(),
+        opening_hours: Vec::new(),
+        season: None,
+        version: 1,
+        created_at: now,
+        updated_at: now,
+        created_by: caller,
+        updated_by: caller,
+    };
+    let location = |legacy: LegacyLocation| Location {
+        id: legacy.id,
+        name: legacy.name,
+        country: legacy.country,
+        site: legacy.site,
+        description: legacy.description,
+        activities: legacy.activities.into_iter().map(activity).collect(),
+        latitude: None,
+        longitude: None,
+        version: 1,
+        created_at: now,
+        updated_at: now,
+        created_by: caller,
+        updated_by: caller,
+    };
+    let transportation = |legacy: LegacyTransportation| Transportation {
+        id: legacy.id,
+        type_: legacy.type_,
+        from: legacy.from,
+        to: legacy.to,
+        cost: legacy.cost,
+        date: legacy.date,
+        version: 1,
+        created_at: now,
+        updated_at: now,
+        created_by: caller,
+        updated_by: caller,
+    };
+
+    for (id, legacy) in LEGACY_LOCATION_STR.with(|m| m.borrow().iter().collect::<Vec<_>>()) {
+        LOCATION_STR.with(|m| m.borrow_mut().insert(id, location(legacy)));
+        LEGACY_LOCATION_STR.with(|m| m.borrow_mut().remove(&id));
+    }
+    for (id, legacy) in LEGACY_ACTIVITY_STR.with(|m| m.borrow().iter().collect::<Vec<_>>()) {
+        ACTIVITY_STR.with(|m| m.borrow_mut().insert(id, activity(legacy)));
+        LEGACY_ACTIVITY_STR.with(|m| m.borrow_mut().remove(&id));
+    }
+    for (id, legacy) in LEGACY_TRANSPORTATION_STR.with(|m| m.borrow().iter().collect::<Vec<_>>()) {
+        TRANSPORTATION_STR.with(|m| m.borrow_mut().insert(id, transportation(legacy)));
+        LEGACY_TRANSPORTATION_STR.with(|m| m.borrow_mut().remove(&id));
+    }
+    for (id, legacy) in LEGACY_TRIP_STR.with(|m| m.borrow().iter().collect::<Vec<_>>()) {
+        let stops = legacy.destinations.len() as i64;
+        let dates = match (parse_date(&legacy.start_date), parse_date(&legacy.end_date)) {
+            (Ok(start), Ok(end)) if start <= end => Some((start, end - start)),
+            _ => None,
+        };
+        // the day part `index` of the trip starts on, or the trip date when the dates are invalid
+        let boundary = |index: i64, fallback: &String| match dates {
+            Some((start, nights)) => format_date(start + nights * index / stops),
+            None => fallback.clone(),
+        };
+        let destinations = legacy
+            .destinations
+            .into_iter()
+            .enumerate()
+            .map(|(index, destination)| Stop {
+                sequence: index as u32 + 1,
+                location: location(destination),
+                arrival_date: boundary(index as i64, &legacy.start_date),
+                departure_date: boundary(index as i64 + 1, &legacy.end_date),
+                accommodation: None,
+            })
+            .collect();
+        let trip = Trip {
+            id,
+            name: legacy.name,
+            start_date: legacy.start_date,
+            end_date: legacy.end_date,
+            destinations,
+            activities: legacy.activities.into_iter().map(activity).collect(),
+            budget: legacy.budget,
+            transportation: legacy.transportation.into_iter().map(transportation).collect(),
+            schedule: Vec::new(),
+            accommodations: Vec::new(),
+            bookings: Vec::new(),
+            status: TripStatus::Planned,
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            created_by: caller,
+            updated_by: caller,
+        };
+        TRIP_STR.with(|m| m.borrow_mut().insert(id, trip));
+        LEGACY_TRIP_STR.with(|m| m.borrow_mut().remove(&id));
+    }
+}
+
+// Trips are only read once the upgrade is over, so a problem with a stored trip
+// cannot make an upgrade fail.
+fn start_timers() {
+    ic_cdk_timers::set_timer_interval(TRASH_PURGE_INTERVAL, purge_expired_trash);
+    ic_cdk_timers::set_timer_interval(NOTIFICATION_CHECK_INTERVAL, check_notifications);
+    ic_cdk_timers::set_timer(Duration::ZERO, arm_status_timers);
+}
+
+fn arm_status_timers() {
+    let trips = TRIP_STR.with(|m| m.borrow().iter().map(|(_, v)| v).collect::<Vec<_>>());
+    for trip in trips.iter() {
+        arm_status_timer(trip);
+    }
+}
 
+// function to see what the canister timers have scheduled (controllers only)
+#[ic_cdk::query]
+fn get_timer_state() -> Result<TimerState,Error> {
+    require_controller()?;
+    let status_changes = STATUS_TIMERS.with(|timers| {
+        timers
+            .borrow()
+            .iter()
+            .map(|(trip_id, timer)| ScheduledStatusChange {
+                trip_id: *trip_id,
+                status: timer.status,
+                at: timer.at,
+            })
+            .collect()
+    });
+    Ok(TimerState {
+        trash_purge_interval_seconds: TRASH_PURGE_INTERVAL.as_secs(),
+        notification_check_interval_seconds: NOTIFICATION_CHECK_INTERVAL.as_secs(),
+        status_changes,
+    })
+}
+
+// The automatic next status of a trip and when it is due: Planned and Booked trips
+// start at midnight (UTC) of their start date, trips in progress complete at the
+// midnight that ends their end date.
+fn next_status_change(trip: &Trip) -> Option<(TripStatus, u64)> {
+    let midnight = |day: i64| (day.max(0) as u64).saturating_mul(NANOS_PER_DAY);
+    match trip.status {
+        TripStatus::Planned | TripStatus::Booked => {
+            parse_date(&trip.start_date).ok().map(|day| (TripStatus::InProgress, midnight(day)))
+        }
+        TripStatus::InProgress => {
+            parse_date(&trip.end_date).ok().map(|day| (TripStatus::Completed, midnight(day + 1)))
+        }
+        _ => None,
+    }
+}
+
+// replace the status timer of a trip with one for its next automatic status change
+fn arm_status_timer(trip: &Trip) {
+    arm_status_timer_after(trip, Duration::ZERO);
+}
+
+// like `arm_status_timer`, but the timer does not fire before `min_delay`
+fn arm_status_timer_after(trip: &Trip, min_delay: Duration) {
+    disarm_status_timer(trip.id);
+    if let Some((status, at)) = next_status_change(trip) {
+        let trip_id = trip.id;
+        let delay = Duration::from_nanos(at.saturating_sub(ic_cdk::api::time())).max(min_delay);
+        let timer = ic_cdk_timers::set_timer(delay, move || apply_status_timer(trip_id));
+        STATUS_TIMERS.with(|timers| timers.borrow_mut().insert(trip_id, StatusTimer { timer, status, at }));
+    }
+}
+
+fn disarm_status_timer(trip_id: u64) {
+    if let Some(old) = STATUS_TIMERS.with(|timers| timers.borrow_mut().remove(&trip_id)) {
+        ic_cdk_timers::clear_timer(old.timer);
+    }
+}
+
+// how long a failed automatic status change waits before it is tried again
+const STATUS_TIMER_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+// timer job: apply the status change of a trip if it is still due, retry it when it fails
+fn apply_status_timer(trip_id: u64) {
+    STATUS_TIMERS.with(|timers| timers.borrow_mut().remove(&trip_id));
+    let mut trip = match get_trip_by_id(trip_id) {
+        Ok(trip) => trip,
+        Err(_) => return,
+    };
+    match next_status_change(&trip) {
+        Some((status, at)) if at <= ic_cdk::api::time() => {
+            let stored = trip.clone();
+            if let Err(err) = change_trip_status(&mut trip, status, "status_timer") {
+                ic_cdk::println!("status timer of trip id={} failed: {}", trip_id, error_message(&err));
+                arm_status_timer_after(&stored, STATUS_TIMER_RETRY_DELAY);
+            }
+        }
+        _ => arm_status_timer(&trip),
+    }
+}
+
+// function to list the deleted entities, optionally of one kind (controllers only)
+#[ic_cdk::query]
+fn list_trash(kind: Option<EntityKind>) -> Result<Vec<TrashEntry>,Error> {
+    require_controller()?;
+    Ok(TRASH_STR.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| kind.is_none() || kind == Some(entry.kind))
+            .collect()
+    }))
+}
+
+// function to bring a deleted entity back from the trash (controllers only)
 #[ic_cdk::update]
-fn update_activity(id: u64, payload: ActivityPayload) -> Result<Activity,Error> {
-    ACTIVITY_STR.with(|m| {
-        let mut activity = m
-            .borrow_mut()
-            .get(&id)
-            .ok_or(Error::NotFound {
-                msg: format!("Activity with the id={} not found", id),
-            })?;
+fn restore(kind: EntityKind, id: u64) -> Result<(),Error> {
+    require_controller()?;
+    let entry = TRASH_STR
+        .with(|m| m.borrow().get(&(kind as u8, id)))
+        .ok_or(Error::NotFound {
+            msg: format!("{:?} with the id={} is not in the trash", kind, id),
+        })?;
+
+    let taken = match &entry.entity {
+        TrashedEntity::Location(_) => LOCATION_STR.with(|m| m.borrow().contains_key(&id)),
+        TrashedEntity::Activity(_) => ACTIVITY_STR.with(|m| m.borrow().contains_key(&id)),
+        TrashedEntity::Trip(_) => TRIP_STR.with(|m| m.borrow().contains_key(&id)),
+        TrashedEntity::Transportation(_) => TRANSPORTATION_STR.with(|m| m.borrow().contains_key(&id)),
+        TrashedEntity::Accommodation(_) => ACCOMMODATION_STR.with(|m| m.borrow().contains_key(&id)),
+        TrashedEntity::Template(_) => TEMPLATE_STR.with(|m| m.borrow().contains_key(&id)),
+        TrashedEntity::JournalEntry(_) => JOURNAL_STR.with(|m| m.borrow().contains_key(&id)),
+        TrashedEntity::PackingTemplate(_) => PACKING_TEMPLATE_STR.with(|m| m.borrow().contains_key(&id)),
+    };
+    if taken {
+        return Err(Error::AlreadyExists {
+            msg: format!("{:?} with the id={} already exists", kind, id),
+        });
+    }
+    match entry.entity {
+        TrashedEntity::Location(mut location) => {
+            location.touch();
+            LOCATION_STR.with(|m| m.borrow_mut().insert(id, location.clone()));
+            record_audit("restore", kind, id, None, Some(&location));
+        }
+        TrashedEntity::Activity(mut activity) => {
+            activity.touch();
+            ACTIVITY_STR.with(|m| m.borrow_mut().insert(id, activity.clone()));
+            record_audit("restore", kind, id, None, Some(&activity));
+        }
+        TrashedEntity::Trip(mut trip) => {
+            // also re-arms the status timer the delete disarmed
+            record_trip_version("restore", &mut trip)?;
+            TRIP_STR.with(|m| m.borrow_mut().insert(id, trip.clone()));
+            record_audit("restore", kind, id, None, Some(&trip));
+        }
+        TrashedEntity::Transportation(mut transportation) => {
+            transportation.touch();
+            TRANSPORTATION_STR.with(|m| m.borrow_mut().insert(id, transportation.clone()));
+            record_audit("restore", kind, id, None, Some(&transportation));
+        }
+        TrashedEntity::Accommodation(mut accommodation) => {
+            accommodation.touch();
+            ACCOMMODATION_STR.with(|m| m.borrow_mut().insert(id, accommodation.clone()));
+            record_audit("restore", kind, id, None, Some(&accommodation));
+        }
+        TrashedEntity::Template(template) => {
+            TEMPLATE_STR.with(|m| m.borrow_mut().insert(id, template.clone()));
+            record_audit("restore", kind, id, None, Some(&template));
+        }
+        TrashedEntity::JournalEntry(mut entry) => {
+            entry.touch();
+            JOURNAL_STR.with(|m| m.borrow_mut().insert(id, entry.clone()));
+            record_audit("restore", kind, id, None, Some(&entry));
+        }
+        TrashedEntity::PackingTemplate(template) => {
+            PACKING_TEMPLATE_STR.with(|m| m.borrow_mut().insert(id, template.clone()));
+            record_audit("restore", kind, id, None, Some(&template));
+        }
+    }
+    TRASH_STR.with(|m| m.borrow_mut().remove(&(kind as u8, id)));
+    Ok(())
+}
+
+// function to delete an entity of the trash for good (controllers only)
+#[ic_cdk::update]
+fn purge(kind: EntityKind, id: u64) -> Result<(),Error> {
+    require_controller()?;
+    if !purge_trash_entry(kind, id) {
+        return Err(Error::NotFound {
+            msg: format!("{:?} with the id={} is not in the trash", kind, id),
+        });
+    }
+    Ok(())
+}
+
+// function to set how many days deleted entities stay in the trash (controllers only)
+#[ic_cdk::update]
+fn set_trash_retention(days: u64) -> Result<(),Error> {
+    require_controller()?;
+    TRASH_RETENTION_DAYS
+        .with(|cell| cell.borrow_mut().set(days))
+        .expect("cannot set the trash retention");
+    Ok(())
+}
+
+// function to get how many days deleted entities stay in the trash
+#[ic_cdk::query]
+fn get_trash_retention() -> u64 {
+    TRASH_RETENTION_DAYS.with(|cell| *cell.borrow().get())
+}
+
+fn move_to_trash(id: u64, entity: TrashedEntity) {
+    let kind = match entity {
+        TrashedEntity::Location(_) => EntityKind::Location,
+        TrashedEntity::Activity(_) => EntityKind::Activity,
+        TrashedEntity::Trip(_) => EntityKind::Trip,
+        TrashedEntity::Transportation(_) => EntityKind::Transportation,
+        TrashedEntity::Accommodation(_) => EntityKind::Accommodation,
+        TrashedEntity::Template(_) => EntityKind::Template,
+        TrashedEntity::JournalEntry(_) => EntityKind::JournalEntry,
+        TrashedEntity::PackingTemplate(_) => EntityKind::PackingTemplate,
+    };
+    let entry = TrashEntry {
+        kind,
+        id,
+        deleted_at: ic_cdk::api::time(),
+        deleted_by: ic_cdk::caller(),
+        entity,
+    };
+    TRASH_STR.with(|m| m.borrow_mut().insert((kind as u8, id), entry));
+}
+
+// remove an entry of the trash and what only lives on for it, returns false when missing
+fn purge_trash_entry(kind: EntityKind, id: u64) -> bool {
+    let entry = match TRASH_STR.with(|m| m.borrow_mut().remove(&(kind as u8, id))) {
+        Some(entry) => entry,
+        None => return false,
+    };
+    if kind == EntityKind::Trip {
+        TRIP_VERSION_STR.with(|m| {
+            let mut versions = m.borrow_mut();
+            let keys = versions.range((id, 0)..=(id, u64::MAX)).map(|(k, _)| k).collect::<Vec<_>>();
+            for key in keys {
+                versions.remove(&key);
+            }
+        });
+        STATUS_HISTORY_STR.with(|m| {
+            let mut history = m.borrow_mut();
+            let keys = history.range((id, 0)..=(id, u64::MAX)).map(|(k, _)| k).collect::<Vec<_>>();
+            for key in keys {
+                history.remove(&key);
+            }
+        });
+        PACKING_STR.with(|m| m.borrow_mut().remove(&id));
+        CHECKLIST_STR.with(|m| m.borrow_mut().remove(&id));
+        JOURNAL_STR.with(|m| {
+            let mut journal = m.borrow_mut();
+            let ids = journal.iter().filter(|(_, entry)| entry.trip_id == id).map(|(k, _)| k).collect::<Vec<_>>();
+            for entry_id in ids {
+                journal.remove(&entry_id);
+            }
+        });
+    }
+    record_audit("purge", kind, id, Some(&entry.entity), None);
+    true
+}
+
+// timer job: purge the entries deleted more than the retention period ago
+fn purge_expired_trash() {
+    let retention = get_trash_retention().saturating_mul(NANOS_PER_DAY);
+    let now = ic_cdk::api::time();
+    let expired = TRASH_STR.with(|m| {
+        m.borrow()
+            .iter()
+            .filter(|(_, entry)| entry.deleted_at.saturating_add(retention) <= now)
+            .map(|(_, entry)| (entry.kind, entry.id))
+            .collect::<Vec<_>>()
+    });
+    for (kind, id) in expired {
+        purge_trash_entry(kind, id);
+    }
+}
+
+const NOTIFICATION_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const NOTIFICATION_MAX_ENTRIES: u64 = 50_000;
+const NOTIFICATION_SENT_RETENTION_DAYS: u64 = 400;
+// longest lead time a user can ask for, one year
+const NOTIFICATION_MAX_LEAD_TIME_HOURS: u32 = 8760;
+const NANOS_PER_HOUR: u64 = 60 * 60 * 1_000_000_000;
+
+// function to get the notifications of the caller, newest first
+#[ic_cdk::query]
+fn get_notifications(unread_only: bool) -> Vec<Notification> {
+    let caller = ic_cdk::caller();
+    let mut notifications = NOTIFICATION_STR.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, n)| n)
+            .filter(|n| n.recipient == caller && !(unread_only && n.read))
+            .collect::<Vec<_>>()
+    });
+    notifications.reverse();
+    notifications
+}
+
+// function to mark notifications of the caller as read
+#[ic_cdk::update]
+fn mark_read(ids: Vec<u64>) -> Result<(),Error> {
+    let caller = ic_cdk::caller();
+    NOTIFICATION_STR.with(|m| {
+        let mut notifications = m.borrow_mut();
+        let mut found = Vec::with_capacity(ids.len());
+        for id in ids.iter() {
+            match notifications.get(id) {
+                Some(notification) if notification.recipient == caller => found.push(notification),
+                _ => {
+                    return Err(Error::NotFound {
+                        msg: format!("Notification with the id={} not found", id),
+                    })
+                }
+            }
+        }
+        for mut notification in found {
+            notification.read = true;
+            notifications.insert(notification.id, notification);
+        }
+        Ok(())
+    })
+}
+
+// function to get the notification preferences of the caller
+#[ic_cdk::query]
+fn get_notification_preferences() -> NotificationPreferences {
+    notification_preferences(&ic_cdk::caller())
+}
+
+// function to set the notification preferences of the caller
+#[ic_cdk::update]
+fn set_notification_preferences(preferences: NotificationPreferences) -> Result<(),Error> {
+    if preferences.budget_threshold_percent == 0 {
+        return Err(Error::InvalidInput {
+            msg: "Budget threshold must be above 0 percent".to_string(),
+        });
+    }
+    if preferences.lead_time_hours > NOTIFICATION_MAX_LEAD_TIME_HOURS {
+        return Err(Error::InvalidInput {
+            msg: format!("Lead time must be at most {} hours", NOTIFICATION_MAX_LEAD_TIME_HOURS),
+        });
+    }
+    NOTIFICATION_PREFERENCES.with(|m| m.borrow_mut().insert(PrincipalKey(ic_cdk::caller()), preferences));
+    Ok(())
+}
+
+fn notification_preferences(user: &Principal) -> NotificationPreferences {
+    NOTIFICATION_PREFERENCES.with(|m| m.borrow().get(&PrincipalKey(*user))).unwrap_or_default()
+}
+
+// Timer job: notify trip owners about cancellation deadlines and check-ins within their
+// lead time, departures within 24 hours and costs over their budget threshold. Each
+// notification is sent once.
+fn check_notifications() {
+    let now = ic_cdk::api::time();
+    let trips = TRIP_STR.with(|m| m.borrow().iter().map(|(_, v)| v).collect::<Vec<_>>());
+    for trip in trips.iter() {
+        if matches!(trip.status, TripStatus::Completed | TripStatus::Cancelled) {
+            continue;
+        }
+        let preferences = notification_preferences(&trip.created_by);
+        let lead_time = (preferences.lead_time_hours as u64).saturating_mul(NANOS_PER_HOUR);
+        let upcoming = |at: u64, window: u64| now <= at && at <= now.saturating_add(window);
+
+        if preferences.cancellation_deadlines {
+            for booking in trip.bookings.iter() {
+                if !matches!(booking.status, BookingStatus::Tentative | BookingStatus::Booked | BookingStatus::Paid) {
+                    continue;
+                }
+                let deadline = match booking.free_cancellation_until.as_ref() {
+                    Some(deadline) => deadline,
+                    None => continue,
+                };
+                if timestamp_of(deadline).is_some_and(|at| upcoming(at, lead_time)) {
+                    notify(
+                        trip,
+                        NotificationKind::CancellationDeadline,
+                        format!("{:?} of trip \"{}\" can be cancelled for free until {}", booking.item, trip.name, deadline),
+                        &format!("deadline:{}:{:?}:{}", trip.id, booking.item, deadline),
+                    );
+                }
+            }
+        }
+        if preferences.check_ins {
+            for accommodation in trip.accommodations.iter() {
+                if timestamp_of(&accommodation.check_in).is_some_and(|at| upcoming(at, lead_time)) {
+                    notify(
+                        trip,
+                        NotificationKind::CheckIn,
+                        format!("Check-in at {} on {}", accommodation.name, accommodation.check_in),
+                        &format!("check-in:{}:{}:{}", trip.id, accommodation.id, accommodation.check_in),
+                    );
+                }
+            }
+        }
+        if preferences.departures {
+            for transportation in trip.transportation.iter() {
+                if timestamp_of(&transportation.date).is_some_and(|at| upcoming(at, 24 * NANOS_PER_HOUR)) {
+                    notify(
+                        trip,
+                        NotificationKind::Departure,
+                        format!("{} of trip \"{}\" departs on {}", transportation.type_, trip.name, transportation.date),
+                        &format!("departure:{}:{}:{}", trip.id, transportation.id, transportation.date),
+                    );
+                }
+            }
+        }
+        if preferences.budget && trip.budget > 0.0 {
+            let threshold = trip.budget * preferences.budget_threshold_percent as f64 / 100.0;
+            if get_total_cost(trip.id).is_ok_and(|cost| cost >= threshold) {
+                notify(
+                    trip,
+                    NotificationKind::BudgetThreshold,
+                    format!(
+                        "Trip \"{}\" has used {}% or more of its budget of {}",
+                        trip.name, preferences.budget_threshold_percent, trip.budget
+                    ),
+                    &format!("budget:{}:{}:{}", trip.id, preferences.budget_threshold_percent, trip.budget),
+                );
+            }
+        }
+    }
+
+    // forget sent notifications after a while
+    let retention = NOTIFICATION_SENT_RETENTION_DAYS * NANOS_PER_DAY;
+    NOTIFICATION_SENT.with(|m| {
+        let mut sent = m.borrow_mut();
+        let expired = sent
+            .iter()
+            .filter(|(_, at)| at.saturating_add(retention) < now)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        for key in expired {
+            sent.remove(&key);
+        }
+    });
+}
+
+// nanoseconds since the epoch of a "YYYY-MM-DD" date, with an optional time part
+fn timestamp_of(value: &str) -> Option<u64> {
+    let (day, minutes) = parse_date_time(value).ok()?;
+    let nanos = day.max(0) as u64 * NANOS_PER_DAY + minutes.unwrap_or(0) as u64 * 60 * 1_000_000_000;
+    Some(nanos)
+}
+
+// add a notification for the owner of a trip unless the same one (`key`) was sent before
+fn notify(trip: &Trip, kind: NotificationKind, message: String, key: &str) {
+    let key = fnv1a(key.as_bytes());
+    if NOTIFICATION_SENT.with(|m| m.borrow().contains_key(&key)) {
+        return;
+    }
+    let now = ic_cdk::api::time();
+    NOTIFICATION_SENT.with(|m| m.borrow_mut().insert(key, now));
+
+    let id = NOTIFICATION_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    })
+    .expect("cannot increment id counter");
+
+    let mut message = message;
+    if message.len() > 512 {
+        let mut end = 512;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+    }
+    let notification = Notification {
+        id,
+        recipient: trip.created_by,
+        trip_id: trip.id,
+        kind,
+        message,
+        created_at: now,
+        read: false,
+    };
+    NOTIFICATION_STR.with(|m| {
+        let mut notifications = m.borrow_mut();
+        notifications.insert(id, notification);
+        if id >= NOTIFICATION_MAX_ENTRIES {
+            notifications.remove(&(id - NOTIFICATION_MAX_ENTRIES));
+        }
+    });
+}
+
+// packing suggestions per activity category of a trip
+const PACKING_SUGGESTIONS: &[(ActivityCategory, &str, PackingCategory)] = &[
+    (ActivityCategory::Hiking, "Hiking boots", PackingCategory::Footwear),
+    (ActivityCategory::Hiking, "Rain jacket", PackingCategory::Clothing),
+    (ActivityCategory::Hiking, "Water bottle", PackingCategory::Gear),
+    (ActivityCategory::Hiking, "First aid kit", PackingCategory::Health),
+    (ActivityCategory::Beach, "Swimsuit", PackingCategory::Clothing),
+    (ActivityCategory::Beach, "Sunscreen", PackingCategory::Health),
+    (ActivityCategory::Beach, "Beach towel", PackingCategory::Gear),
+    (ActivityCategory::Sport, "Sportswear", PackingCategory::Clothing),
+    (ActivityCategory::Sport, "Sport shoes", PackingCategory::Footwear),
+    (ActivityCategory::Nightlife, "Evening outfit", PackingCategory::Clothing),
+    (ActivityCategory::Sightseeing, "Comfortable walking shoes", PackingCategory::Footwear),
+    (ActivityCategory::Tour, "Comfortable walking shoes", PackingCategory::Footwear),
+    (ActivityCategory::Museum, "Comfortable walking shoes", PackingCategory::Footwear),
+    (ActivityCategory::Shopping, "Foldable bag", PackingCategory::Gear),
+];
+
+// function to get the packing list of a trip
+#[ic_cdk::query]
+fn get_packing_list(trip_id: u64) -> Result<PackingList,Error> {
+    get_trip_by_id(trip_id)?;
+    Ok(packing_list_of(trip_id))
+}
+
+// function to add an item to the packing list of a trip
+#[ic_cdk::update]
+fn add_packing_item(trip_id: u64, payload: PackingItemPayload) -> Result<PackingList,Error> {
+    get_trip_by_id(trip_id)?;
+    payload.validate()?;
+    let mut list = packing_list_of(trip_id);
+    push_packing_item(&mut list, payload);
+    save_packing_list("add_packing_item", list)
+}
+
+// function to update an item of the packing list of a trip
+#[ic_cdk::update]
+fn update_packing_item(trip_id: u64, item_id: u32, payload: PackingItemPayload) -> Result<PackingList,Error> {
+    get_trip_by_id(trip_id)?;
+    payload.validate()?;
+    let mut list = packing_list_of(trip_id);
+    let item = packing_item_mut(&mut list, item_id)?;
+    item.name = payload.name.trim().to_string();
+    item.quantity = payload.quantity;
+    item.category = payload.category;
+    item.traveler = payload.traveler;
+    save_packing_list("update_packing_item", list)
+}
 
-        activity.name = payload.name;
-        activity.duration = payload.duration;
-        activity.cost = payload.cost;
-        activity.description = payload.description;
-        activity.location = payload.location;
+// function to mark an item of the packing list of a trip as packed or not
+#[ic_cdk::update]
+fn set_item_packed(trip_id: u64, item_id: u32, packed: bool) -> Result<PackingList,Error> {
+    get_trip_by_id(trip_id)?;
+    let mut list = packing_list_of(trip_id);
+    packing_item_mut(&mut list, item_id)?.packed = packed;
+    save_packing_list("set_item_packed", list)
+}
 
-        m.borrow_mut().insert(id, activity.clone());
-        Ok(activity)
-    })
+// function to remove an item from the packing list of a trip
+#[ic_cdk::update]
+fn remove_packing_item(trip_id: u64, item_id: u32) -> Result<PackingList,Error> {
+    get_trip_by_id(trip_id)?;
+    let mut list = packing_list_of(trip_id);
+    packing_item_mut(&mut list, item_id)?;
+    list.items.retain(|item| item.id != item_id);
+    save_packing_list("remove_packing_item", list)
 }
 
-// function to delete an activity
+// function to add the items of a packing template that are not on the list of a trip yet
 #[ic_cdk::update]
-fn delete_activity(id: u64) -> Result<(),Error> {
-    ACTIVITY_STR.with(|m| {
-        m.borrow_mut()
-            .remove(&id)
-            .ok_or(Error::NotFound {
-                msg: format!("Activity with the id={} not found", id),
-            })
-            .map(|_| ())
-    })
+fn apply_packing_template(trip_id: u64, template_id: u64) -> Result<PackingList,Error> {
+    get_trip_by_id(trip_id)?;
+    let template = get_packing_template_by_id(template_id)?;
+    let mut list = packing_list_of(trip_id);
+    for payload in template.items {
+        if !has_packing_item(&list, &payload.name) {
+            push_packing_item(&mut list, payload);
+        }
+    }
+    save_packing_list("apply_packing_template", list)
 }
 
-// function to get all trips
+// Function to suggest packing items for a trip from its length and the categories of
+// its activities. Items already on the packing list are left out.
 #[ic_cdk::query]
-fn get_trips() -> Result<Vec<Trip>,Error> {
+fn suggest_packing_items(trip_id: u64) -> Result<Vec<PackingItemPayload>,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let list = packing_list_of(trip_id);
+    let days = (parse_date(&trip.end_date)? - parse_date(&trip.start_date)? + 1).max(1) as u32;
 
-    let trips = TRIP_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
-    if trips.len() == 0 {
-        return Err(Error::NotFound { msg: "No Trips  found".to_string() });
+    let mut suggestions: Vec<PackingItemPayload> = Vec::new();
+    let mut suggest = |name: &str, quantity: u32, category: PackingCategory| {
+        if !has_packing_item(&list, name) && !suggestions.iter().any(|s| s.name.eq_ignore_ascii_case(name)) {
+            suggestions.push(PackingItemPayload { name: name.to_string(), quantity, category, traveler: None });
+        }
+    };
+    suggest("Passport", 1, PackingCategory::Documents);
+    suggest("Phone charger", 1, PackingCategory::Electronics);
+    suggest("Toothbrush", 1, PackingCategory::Toiletries);
+    // clothes for a week at most, longer trips do laundry
+    suggest("T-shirts", days.min(7), PackingCategory::Clothing);
+    suggest("Underwear", (days + 1).min(8), PackingCategory::Clothing);
+    suggest("Socks", (days + 1).min(8), PackingCategory::Clothing);
+    if days > 7 {
+        suggest("Laundry bag", 1, PackingCategory::Gear);
     }
-    Ok(trips)
+    if trip.transportation.iter().any(|t| t.type_.to_lowercase().contains("flight") || t.type_.to_lowercase().contains("plane")) {
+        suggest("Travel pillow", 1, PackingCategory::Gear);
+    }
+    for (category, name, packing_category) in PACKING_SUGGESTIONS {
+        if trip.activities.iter().any(|a| a.category == *category) {
+            suggest(name, 1, *packing_category);
+        }
+    }
+    Ok(suggestions)
 }
 
-// function to get trip by id
+// function to get all packing templates
 #[ic_cdk::query]
-fn get_trip_by_id(id: u64) -> Result<Trip,Error> {
-    TRIP_STR.with(|service| {
+fn get_packing_templates() -> Result<Vec<PackingTemplate>,Error> {
+
+    let templates = PACKING_TEMPLATE_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
+    if templates.is_empty() {
+        return Err(Error::NotFound { msg: "No Packing Templates  found".to_string() });
+    }
+    Ok(templates)
+}
+
+// function to get packing template by id
+#[ic_cdk::query]
+fn get_packing_template_by_id(id: u64) -> Result<PackingTemplate,Error> {
+    PACKING_TEMPLATE_STR.with(|service| {
         service
-            .borrow_mut()
+            .borrow()
             .get(&id)
             .ok_or(Error::NotFound {
-                msg: format!("Trip with the  id={} not found", id),
+                msg: format!("Packing Template with the  id={} not found", id),
             })
     })
 }
 
-// function to add a trip
+// function to add a packing template
 #[ic_cdk::update]
-fn add_trip(payload: TripPayload) -> Result<Trip,Error> {
-
-    // validate user payload all fields are required
+fn add_packing_template(name: String, items: Vec<PackingItemPayload>) -> Result<PackingTemplate,Error> {
+    if name.trim().is_empty() {
+        return Err(Error::InvalidInput { msg: "Template name is required".to_string() });
+    }
+    for item in items.iter() {
+        item.validate()?;
+    }
+    let mut template = PackingTemplate { id: 0, name, items };
+    check_record_size("Packing Template", template.id, &template)?;
 
-    let id = TRIP_ID
+    let id = PACKING_TEMPLATE_ID
     .with(|counter| {
         let current_value = *counter.borrow().get();
         counter.borrow_mut().set(current_value + 1)
     })
     .expect("cannot increment id counter");
 
-    let trip = Trip {
-        id,
-        name: payload.name,
-        start_date: payload.start_date,
-        end_date: payload.end_date,
-        destinations: Vec::new(),
-        activities: Vec::new(),
-        budget: payload.budget,
-        transportation: Vec::new(),
-    };
-
-    TRIP_STR.with(|m| m.borrow_mut().insert(id, trip.clone()));
-    Ok(trip)
+    template.id = id;
+    PACKING_TEMPLATE_STR.with(|m| m.borrow_mut().insert(id, template.clone()));
+    record_audit("add_packing_template", EntityKind::PackingTemplate, id, None, Some(&template));
+    Ok(template)
 }
 
-// function to update a trip
+// function to save the packing list of a trip as a template
 #[ic_cdk::update]
-fn update_trip(id: u64, payload: TripPayload) -> Result<Trip,Error> {
-    TRIP_STR.with(|m| {
-        let mut trip = m
-            .borrow_mut()
-            .get(&id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", id),
-            })?;
-
-        trip.name = payload.name;
-        trip.start_date = payload.start_date;
-        trip.end_date = payload.end_date;
-        trip.budget = payload.budget;
-
-        m.borrow_mut().insert(id, trip.clone());
-        Ok(trip)
-    })
+fn save_packing_template(trip_id: u64, name: String) -> Result<PackingTemplate,Error> {
+    get_trip_by_id(trip_id)?;
+    let items = packing_list_of(trip_id)
+        .items
+        .into_iter()
+        .map(|item| PackingItemPayload {
+            name: item.name,
+            quantity: item.quantity,
+            category: item.category,
+            traveler: item.traveler,
+        })
+        .collect();
+    add_packing_template(name, items)
 }
 
-// function to delete a trip
+// function to delete a packing template
 #[ic_cdk::update]
-fn delete_trip(id: u64) -> Result<(),Error> {
-    TRIP_STR.with(|m| {
+fn delete_packing_template(id: u64) -> Result<(),Error> {
+    PACKING_TEMPLATE_STR.with(|m| {
         m.borrow_mut()
             .remove(&id)
             .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", id),
+                msg: format!("Packing Template with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_packing_template", EntityKind::PackingTemplate, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::PackingTemplate(before));
             })
-            .map(|_| ())
     })
 }
 
-// function to get all transportation
-#[ic_cdk::query]
-fn get_transportations() -> Result<Vec<Transportation>,Error> {
-
-    let transportations = TRANSPORTATION_STR.with(|m| m.borrow().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>());
-    if transportations.len() == 0 {
-        return Err(Error::NotFound { msg: "No Transportations  found".to_string() });
-    }
-    Ok(transportations)
+fn packing_list_of(trip_id: u64) -> PackingList {
+    PACKING_STR
+        .with(|m| m.borrow().get(&trip_id))
+        .unwrap_or(PackingList { trip_id, items: Vec::new(), next_item_id: 0 })
 }
 
-// function to get transportation by id
-#[ic_cdk::query]
-fn get_transportation_by_id(id: u64) -> Result<Transportation,Error> {
-    TRANSPORTATION_STR.with(|service| {
-        service
-            .borrow_mut()
-            .get(&id)
-            .ok_or(Error::NotFound {
-                msg: format!("Transportation with the  id={} not found", id),
-            })
+fn packing_item_mut(list: &mut PackingList, item_id: u32) -> Result<&mut PackingItem,Error> {
+    let trip_id = list.trip_id;
+    list.items.iter_mut().find(|item| item.id == item_id).ok_or(Error::NotFound {
+        msg: format!("Packing item with the id={} is not on the list of trip id={}", item_id, trip_id),
     })
 }
 
-// function to add a transportation
-#[ic_cdk::update]
-fn add_transportation(payload: TransportationPayload) -> Result<Transportation,Error> {
+fn has_packing_item(list: &PackingList, name: &str) -> bool {
+    list.items.iter().any(|item| item.name.eq_ignore_ascii_case(name.trim()))
+}
 
-    // validate user payload all fields are required
+fn push_packing_item(list: &mut PackingList, payload: PackingItemPayload) {
+    list.items.push(PackingItem {
+        id: list.next_item_id,
+        name: payload.name.trim().to_string(),
+        quantity: payload.quantity,
+        category: payload.category,
+        packed: false,
+        traveler: payload.traveler,
+    });
+    list.next_item_id += 1;
+}
 
-    let id = TRANSPORTATION_ID
-    .with(|counter| {
-        let current_value = *counter.borrow().get();
-        counter.borrow_mut().set(current_value + 1)
-    })
-    .expect("cannot increment id counter");
+// store a packing list, the change is audited as a change of its trip
+fn save_packing_list(endpoint: &str, list: PackingList) -> Result<PackingList,Error> {
+    check_record_size("Packing List", list.trip_id, &list)?;
+    let before = PACKING_STR.with(|m| m.borrow_mut().insert(list.trip_id, list.clone()));
+    record_audit(endpoint, EntityKind::Trip, list.trip_id, before.as_ref(), Some(&list));
+    Ok(list)
+}
 
-    let transportation = Transportation {
-        id,
-        type_: payload.type_,
-        from: payload.from,
-        to: payload.to,
-        cost: payload.cost,
-        date: payload.date,
-    };
+// function to get the checklist of a trip, ordered by due date
+#[ic_cdk::query]
+fn get_checklist(trip_id: u64) -> Result<Vec<TaskView>,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let mut tasks = checklist_of(trip_id)
+        .tasks
+        .into_iter()
+        .map(|task| task_view(&trip, task))
+        .collect::<Vec<_>>();
+    sort_tasks(&mut tasks);
+    Ok(tasks)
+}
 
-    TRANSPORTATION_STR.with(|m| m.borrow_mut().insert(id, transportation.clone()));
-    Ok(transportation)
+// function to get the open tasks of all trips of the caller, ordered by due date
+#[ic_cdk::query]
+fn get_open_tasks() -> Vec<TaskView> {
+    let caller = ic_cdk::caller();
+    let trips = TRIP_STR.with(|m| {
+        m.borrow()
+            .iter()
+            .filter(|(_, trip)| trip.created_by == caller)
+            .filter(|(_, trip)| !matches!(trip.status, TripStatus::Completed | TripStatus::Cancelled))
+            .map(|(_, trip)| trip)
+            .collect::<Vec<_>>()
+    });
+    let mut tasks = Vec::new();
+    for trip in trips.iter() {
+        for task in checklist_of(trip.id).tasks {
+            if !task.done {
+                tasks.push(task_view(trip, task));
+            }
+        }
+    }
+    sort_tasks(&mut tasks);
+    tasks
 }
 
-// function to update a transportation
+// function to add a task to the checklist of a trip
 #[ic_cdk::update]
-fn update_transportation(id: u64, payload: TransportationPayload) -> Result<Transportation,Error> {
-    TRANSPORTATION_STR.with(|m| {
-        let mut transportation = m
-            .borrow_mut()
-            .get(&id)
-            .ok_or(Error::NotFound {
-                msg: format!("Transportation with the id={} not found", id),
-            })?;
+fn add_task(trip_id: u64, payload: TaskPayload) -> Result<TaskView,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    payload.validate()?;
+    let mut checklist = checklist_of(trip_id);
+    let task = TripTask {
+        id: checklist.next_task_id,
+        title: payload.title.trim().to_string(),
+        due: payload.due.map(|due| due.trim().to_string()),
+        assignees: payload.assignees,
+        done: false,
+        completed_at: None,
+        completed_by: None,
+    };
+    checklist.next_task_id += 1;
+    checklist.tasks.push(task.clone());
+    save_checklist("add_task", checklist)?;
+    Ok(task_view(&trip, task))
+}
 
-        transportation.type_ = payload.type_;
-        transportation.from = payload.from;
-        transportation.to = payload.to;
-        transportation.cost = payload.cost;
-        transportation.date = payload.date;
+// function to update a task of the checklist of a trip
+#[ic_cdk::update]
+fn update_task(trip_id: u64, task_id: u32, payload: TaskPayload) -> Result<TaskView,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    payload.validate()?;
+    let mut checklist = checklist_of(trip_id);
+    let task = task_mut(&mut checklist, task_id)?;
+    task.title = payload.title.trim().to_string();
+    task.due = payload.due.map(|due| due.trim().to_string());
+    task.assignees = payload.assignees;
+    let task = task.clone();
+    save_checklist("update_task", checklist)?;
+    Ok(task_view(&trip, task))
+}
 
-        m.borrow_mut().insert(id, transportation.clone());
-        Ok(transportation)
-    })
+// function to mark a task of the checklist of a trip as done or open again
+#[ic_cdk::update]
+fn set_task_done(trip_id: u64, task_id: u32, done: bool) -> Result<TaskView,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let mut checklist = checklist_of(trip_id);
+    let task = task_mut(&mut checklist, task_id)?;
+    if task.done != done {
+        task.done = done;
+        task.completed_at = done.then(ic_cdk::api::time);
+        task.completed_by = done.then(ic_cdk::caller);
+    }
+    let task = task.clone();
+    save_checklist("set_task_done", checklist)?;
+    Ok(task_view(&trip, task))
 }
 
-// function to delete a transportation
+// function to remove a task from the checklist of a trip
 #[ic_cdk::update]
-fn delete_transportation(id: u64) -> Result<(),Error> {
-    TRANSPORTATION_STR.with(|m| {
-        m.borrow_mut()
-            .remove(&id)
-            .ok_or(Error::NotFound {
-                msg: format!("Transportation with the id={} not found", id),
-            })
-            .map(|_| ())
+fn remove_task(trip_id: u64, task_id: u32) -> Result<(),Error> {
+    get_trip_by_id(trip_id)?;
+    let mut checklist = checklist_of(trip_id);
+    task_mut(&mut checklist, task_id)?;
+    checklist.tasks.retain(|task| task.id != task_id);
+    save_checklist("remove_task", checklist)?;
+    Ok(())
+}
+
+fn checklist_of(trip_id: u64) -> Checklist {
+    CHECKLIST_STR
+        .with(|m| m.borrow().get(&trip_id))
+        .unwrap_or(Checklist { trip_id, tasks: Vec::new(), next_task_id: 0 })
+}
+
+fn task_mut(checklist: &mut Checklist, task_id: u32) -> Result<&mut TripTask,Error> {
+    let trip_id = checklist.trip_id;
+    checklist.tasks.iter_mut().find(|task| task.id == task_id).ok_or(Error::NotFound {
+        msg: format!("Task with the id={} is not on the checklist of trip id={}", task_id, trip_id),
     })
 }
 
-// function to add an activity to a location
-#[ic_cdk::update]
-fn add_activity_to_location(location_id: u64, activity_id: u64) -> Result<(),Error> {
-    LOCATION_STR.with(|m| {
-        let mut location = m
-            .borrow_mut()
-            .get(&location_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Location with the id={} not found", location_id),
-            })?;
+// store a checklist, the change is audited as a change of its trip
+fn save_checklist(endpoint: &str, checklist: Checklist) -> Result<(),Error> {
+    check_record_size("Checklist", checklist.trip_id, &checklist)?;
+    let before = CHECKLIST_STR.with(|m| m.borrow_mut().insert(checklist.trip_id, checklist.clone()));
+    record_audit(endpoint, EntityKind::Trip, checklist.trip_id, before.as_ref(), Some(&checklist));
+    Ok(())
+}
 
-        let activity = ACTIVITY_STR.with(|m| {
-            m.borrow_mut()
-                .get(&activity_id)
-                .ok_or(Error::NotFound {
-                    msg: format!("Activity with the id={} not found", activity_id),
-                })
-        })?;
+// resolve the due date of a task against the start date of its trip
+fn task_view(trip: &Trip, task: TripTask) -> TaskView {
+    let due_day = task
+        .due
+        .as_ref()
+        .and_then(|due| Some(parse_date(&trip.start_date).ok()? + parse_relative_days(due).ok()?));
+    let today = (ic_cdk::api::time() / NANOS_PER_DAY) as i64;
+    TaskView {
+        trip_id: trip.id,
+        trip_name: trip.name.clone(),
+        due_date: due_day.map(format_date),
+        overdue: !task.done && due_day.is_some_and(|day| day < today),
+        task,
+    }
+}
 
-        location.activities.push(activity.clone());
-        m.borrow_mut().insert(location_id, location.clone());
-        Ok(())
+// tasks with a due date first, earliest first
+fn sort_tasks(tasks: &mut [TaskView]) {
+    tasks.sort_by(|a, b| {
+        (a.due_date.is_none(), &a.due_date, a.trip_id, a.task.id).cmp(&(b.due_date.is_none(), &b.due_date, b.trip_id, b.task.id))
+    });
+}
+
+// parse an offset like "-30 days", "+2 weeks" or "0 days" into a number of days
+fn parse_relative_days(value: &str) -> Result<i64,Error> {
+    let invalid = || Error::InvalidInput {
+        msg: format!("Invalid due date '{}', expected an offset like \"-30 days\" or \"-2 weeks\"", value),
+    };
+    let mut parts = value.split_whitespace();
+    let amount = parts.next().ok_or_else(invalid)?;
+    let unit = parts.next().ok_or_else(invalid)?;
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    let amount = amount.strip_prefix('+').unwrap_or(amount).parse::<i64>().map_err(|_| invalid())?;
+    let days = match unit.to_lowercase().as_str() {
+        "day" | "days" => Some(amount),
+        "week" | "weeks" => amount.checked_mul(7),
+        _ => return Err(invalid()),
+    }
+    .ok_or_else(invalid)?;
+    if days.checked_abs().is_none_or(|days| days > 3650) {
+        return Err(invalid());
+    }
+    Ok(days)
+}
+
+// function to get the journal entries of a trip, optionally of one day, in chronological order
+#[ic_cdk::query]
+fn get_journal_entries(trip_id: u64, date: Option<String>) -> Result<Vec<JournalEntry>,Error> {
+    get_trip_by_id(trip_id)?;
+    if let Some(date) = date.as_ref() {
+        parse_date(date)?;
+    }
+    Ok(journal_of(trip_id, date.as_deref()))
+}
+
+// function to get journal entry by id
+#[ic_cdk::query]
+fn get_journal_entry_by_id(id: u64) -> Result<JournalEntry,Error> {
+    JOURNAL_STR.with(|service| {
+        service
+            .borrow()
+            .get(&id)
+            .ok_or(Error::NotFound {
+                msg: format!("Journal Entry with the  id={} not found", id),
+            })
     })
 }
 
-// function to add a destination to a trip
+// function to write a journal entry
 #[ic_cdk::update]
-fn add_destination_to_trip(trip_id: u64, location_id: u64) -> Result<(),Error> {
-    TRIP_STR.with(|m| {
-        let mut trip = m
-            .borrow_mut()
-            .get(&trip_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
+fn add_journal_entry(payload: JournalEntryPayload) -> Result<JournalEntry,Error> {
 
-        let location = LOCATION_STR.with(|m| {
-            m.borrow_mut()
-                .get(&location_id)
-                .ok_or(Error::NotFound {
-                    msg: format!("Location with the id={} not found", location_id),
-                })
-        })?;
+    payload.validate()?;
 
-        trip.destinations.push(location.clone());
-        m.borrow_mut().insert(trip_id, trip.clone());
-        Ok(())
+    let mut entry = JournalEntry {
+        id: 0,
+        trip_id: payload.trip_id,
+        date: payload.date.trim().to_string(),
+        location_id: payload.location_id,
+        activity_id: payload.activity_id,
+        text: payload.text,
+        mood: payload.mood,
+        rating: payload.rating,
+        tags: normalize_tags(payload.tags),
+        version: 1,
+        created_at: ic_cdk::api::time(),
+        updated_at: ic_cdk::api::time(),
+        created_by: ic_cdk::caller(),
+        updated_by: ic_cdk::caller(),
+    };
+    check_record_size("Journal Entry", entry.id, &entry)?;
+
+    let id = JOURNAL_ID
+    .with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
     })
+    .expect("cannot increment id counter");
+    entry.id = id;
+
+    JOURNAL_STR.with(|m| m.borrow_mut().insert(id, entry.clone()));
+    record_audit("add_journal_entry", EntityKind::JournalEntry, id, None, Some(&entry));
+    Ok(entry)
 }
 
-// function to add an activity to a trip
+// function to edit a journal entry
 #[ic_cdk::update]
-fn add_activity_to_trip(trip_id: u64, activity_id: u64) -> Result<(),Error> {
-    TRIP_STR.with(|m| {
-        let mut trip = m
+fn update_journal_entry(id: u64, payload: JournalEntryPayload, expected_version: Option<u64>) -> Result<JournalEntry,Error> {
+    payload.validate()?;
+    JOURNAL_STR.with(|m| {
+        let mut entry = m
             .borrow_mut()
-            .get(&trip_id)
+            .get(&id)
             .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
+                msg: format!("Journal Entry with the id={} not found", id),
             })?;
+        check_version("Journal Entry", id, entry.version, expected_version)?;
 
-        let activity = ACTIVITY_STR.with(|m| {
-            m.borrow_mut()
-                .get(&activity_id)
-                .ok_or(Error::NotFound {
-                    msg: format!("Activity with the id={} not found", activity_id),
-                })
-        })?;
+        entry.trip_id = payload.trip_id;
+        entry.date = payload.date.trim().to_string();
+        entry.location_id = payload.location_id;
+        entry.activity_id = payload.activity_id;
+        entry.text = payload.text;
+        entry.mood = payload.mood;
+        entry.rating = payload.rating;
+        entry.tags = normalize_tags(payload.tags);
 
-        trip.activities.push(activity.clone());
-        m.borrow_mut().insert(trip_id, trip.clone());
-        Ok(())
+        entry.touch();
+        check_record_size("Journal Entry", id, &entry)?;
+        let before = m.borrow_mut().insert(id, entry.clone());
+        record_audit("update_journal_entry", EntityKind::JournalEntry, id, before.as_ref(), Some(&entry));
+        Ok(entry)
     })
 }
 
-// function to add a transportation to a trip
+// function to delete a journal entry
 #[ic_cdk::update]
-fn add_transportation_to_trip(trip_id: u64, transportation_id: u64) -> Result<(),Error> {
-    TRIP_STR.with(|m| {
-        let mut trip = m
-            .borrow_mut()
-            .get(&trip_id)
+fn delete_journal_entry(id: u64) -> Result<(),Error> {
+    JOURNAL_STR.with(|m| {
+        m.borrow_mut()
+            .remove(&id)
             .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
-
-        let transportation = TRANSPORTATION_STR.with(|m| {
-            m.borrow_mut()
-                .get(&transportation_id)
-                .ok_or(Error::NotFound {
-                    msg: format!("Transportation with the id={} not found", transportation_id),
-                })
-        })?;
-
-        trip.transportation.push(transportation.clone());
-        m.borrow_mut().insert(trip_id, trip.clone());
-        Ok(())
+                msg: format!("Journal Entry with the id={} not found", id),
+            })
+            .map(|before| {
+                record_audit("delete_journal_entry", EntityKind::JournalEntry, id, Some(&before), None);
+                move_to_trash(id, TrashedEntity::JournalEntry(before));
+            })
     })
 }
 
-// function to get all activities for a location
+// Function to get a day of a trip: the stops it is spent at, its scheduled activities
+// by start time, the transportation leaving that day, the accommodations stayed in
+// that night and the journal entries written about it.
 #[ic_cdk::query]
-fn get_activities_for_location(location_id: u64) -> Result<Vec<Activity>,Error> {
-    LOCATION_STR.with(|service| {
-        let location = service
-            .borrow_mut()
-            .get(&location_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Location with the id={} not found", location_id),
-            })?;
+fn get_day_summary(trip_id: u64, date: String) -> Result<DaySummary,Error> {
+    let trip = get_trip_by_id(trip_id)?;
+    let day = parse_date(&date)?;
+    let start = parse_date(&trip.start_date)?;
+    if day < start || day > parse_date(&trip.end_date)? {
+        return Err(Error::InvalidInput {
+            msg: format!("Date {} is outside of the trip ({} to {})", date, trip.start_date, trip.end_date),
+        });
+    }
+    let on_day = |value: &str| parse_date_time(value).is_ok_and(|(d, _)| d == day);
+    let within = |from: &str, to: &str| parse_date(from).is_ok_and(|from| from <= day) && parse_date(to).is_ok_and(|to| day <= to);
 
-        Ok(location.activities.clone())
+    let stops = trip
+        .destinations
+        .iter()
+        .filter(|stop| within(&stop.arrival_date, &stop.departure_date))
+        .cloned()
+        .collect();
+    let mut activities = trip
+        .schedule
+        .iter()
+        .filter(|slot| on_day(&slot.date))
+        .filter_map(|slot| {
+            let activity = trip.activities.iter().find(|a| a.id == slot.activity_id)?;
+            Some(DayActivity { activity: activity.clone(), start_time: slot.start_time.clone() })
+        })
+        .collect::<Vec<_>>();
+    activities.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    let mut transportation = trip
+        .transportation
+        .iter()
+        .filter(|t| on_day(&t.date))
+        .cloned()
+        .collect::<Vec<_>>();
+    transportation.sort_by(|a, b| a.date.cmp(&b.date));
+    // the night of check-out is spent elsewhere
+    let accommodations = trip
+        .accommodations
+        .iter()
+        .filter(|a| within(&a.check_in, &a.check_out) && !on_day(&a.check_out))
+        .cloned()
+        .collect();
+
+    Ok(DaySummary {
+        trip_id,
+        date: format_date(day),
+        day: (day - start + 1) as u32,
+        stops,
+        activities,
+        transportation,
+        accommodations,
+        journal: journal_of(trip_id, Some(&format_date(day))),
     })
 }
 
-// function to get all destinations for a trip
-#[ic_cdk::query]
-fn get_destinations_for_trip(trip_id: u64) -> Result<Vec<Location>,Error> {
-    TRIP_STR.with(|service| {
-        let trip = service
-            .borrow_mut()
-            .get(&trip_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
+// journal entries of a trip by date and then by writing time
+fn journal_of(trip_id: u64, date: Option<&str>) -> Vec<JournalEntry> {
+    let day = date.and_then(|date| parse_date(date).ok());
+    let mut entries = JOURNAL_STR.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| entry.trip_id == trip_id)
+            .filter(|entry| day.is_none() || parse_date(&entry.date).ok() == day)
+            .collect::<Vec<_>>()
+    });
+    entries.sort_by(|a, b| (&a.date, a.created_at, a.id).cmp(&(&b.date, b.created_at, b.id)));
+    entries
+}
 
-        Ok(trip.destinations.clone())
-    })
+// function to create (or rotate) the share token of a trip (its creator only)
+#[ic_cdk::update]
+async fn create_share_token(trip_id: u64) -> Result<String,Error> {
+    require_trip_owner(&get_trip_by_id(trip_id)?)?;
+
+    let (bytes,) = match ic_cdk::api::management_canister::main::raw_rand().await {
+        Ok(bytes) => bytes,
+        Err((_, msg)) => ic_cdk::trap(&format!("cannot generate a share token: {}", msg)),
+    };
+    let token = bytes.iter().take(16).map(|b| format!("{:02x}", b)).collect::<String>();
+
+    // the trip may have been deleted while waiting for randomness
+    get_trip_by_id(trip_id)?;
+    SHARE_TOKEN_STR.with(|m| {
+        m.borrow_mut().insert(
+            trip_id,
+            ShareToken {
+                token: token.clone(),
+                created_at: ic_cdk::api::time(),
+            },
+        )
+    });
+    // the token itself is a secret and is not written to the audit log
+    record_audit("create_share_token", EntityKind::Trip, trip_id, None::<&Trip>, None);
+    Ok(token)
 }
 
-// function to get all activities for a trip
-#[ic_cdk::query]
-fn get_activities_for_trip(trip_id: u64) -> Result<Vec<Activity>,Error> {
-    TRIP_STR.with(|service| {
-        let trip = service
-            .borrow_mut()
-            .get(&trip_id)
+// function to revoke the share token of a trip (its creator only)
+#[ic_cdk::update]
+fn revoke_share_token(trip_id: u64) -> Result<(),Error> {
+    require_trip_owner(&get_trip_by_id(trip_id)?)?;
+    SHARE_TOKEN_STR.with(|m| {
+        m.borrow_mut()
+            .remove(&trip_id)
             .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
-
-        Ok(trip.activities.clone())
+                msg: format!("Trip with the id={} is not shared", trip_id),
+            })
+            .map(|_| record_audit("revoke_share_token", EntityKind::Trip, trip_id, None::<&Trip>, None))
     })
 }
 
-// function to get all transportation for a trip
+// read-only HTTP access to shared trips:
+//   GET /trips/{id}.json, /trips/{id}.ics and /trips/{id}/itinerary.html
+// every request must carry the trip share token as `?token=...`
 #[ic_cdk::query]
-fn get_transportation_for_trip(trip_id: u64) -> Result<Vec<Transportation>,Error> {
-    TRIP_STR.with(|service| {
-        let trip = service
-            .borrow_mut()
-            .get(&trip_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
+fn http_request(request: HttpRequest) -> HttpResponse {
+    if request.method != "GET" && request.method != "HEAD" {
+        let mut response = http_error(405, "Method Not Allowed");
+        response.headers.push(("Allow".to_string(), "GET, HEAD".to_string()));
+        return response;
+    }
 
-        Ok(trip.transportation.clone())
+    let (path, query) = request.url.split_once('?').unwrap_or((request.url.as_str(), ""));
+    let route = path
+        .strip_prefix("/trips/")
+        .and_then(|rest| {
+            if let Some(id) = rest.strip_suffix("/itinerary.html") {
+                Some((id, "html"))
+            } else if let Some(id) = rest.strip_suffix(".json") {
+                Some((id, "json"))
+            } else {
+                rest.strip_suffix(".ics").map(|id| (id, "ics"))
+            }
+        })
+        .and_then(|(id, format)| id.parse::<u64>().ok().map(|id| (id, format)));
+    let (trip_id, format) = match route {
+        Some(route) => route,
+        None => return http_error(404, "Not Found"),
+    };
+
+    let token = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "token")
+        .map(|(_, value)| value)
+        .unwrap_or("");
+    let shared = SHARE_TOKEN_STR.with(|m| m.borrow().get(&trip_id));
+    match shared {
+        Some(shared) if tokens_match(&shared.token, token) => {}
+        _ => return http_error(403, "Forbidden"),
+    }
+
+    let trip = match get_trip_by_id(trip_id) {
+        Ok(trip) => trip,
+        Err(_) => return http_error(404, "Not Found"),
+    };
+    let (content_type, body) = match format {
+        "json" => ("application/json; charset=utf-8", serde_json::to_string_pretty(&trip).unwrap_or_default()),
+        "ics" => ("text/calendar; charset=utf-8", render_trip_ics(&trip)),
+        _ => ("text/html; charset=utf-8", render_trip_html(&trip)),
+    };
+
+    let etag = format!("\"{:016x}\"", fnv1a(body.as_bytes()));
+    let mut headers = vec![
+        ("Content-Type".to_string(), content_type.to_string()),
+        ("Cache-Control".to_string(), "private, max-age=300".to_string()),
+        ("ETag".to_string(), etag.clone()),
+        ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+    ];
+    if format == "ics" {
+        headers.push((
+            "Content-Disposition".to_string(),
+            format!("inline; filename=\"trip-{}.ics\"", trip_id),
+        ));
+    }
+
+    let not_modified = request
+        .headers
+        .iter()
+        .any(|(name, value)| name.eq_ignore_ascii_case("If-None-Match") && *value == etag);
+    if not_modified {
+        return HttpResponse { status_code: 304, headers, body: Vec::new() };
+    }
+
+    // a HEAD response reports the length of the body a GET would get
+    headers.push(("Content-Length".to_string(), body.len().to_string()));
+    let body = if request.method == "HEAD" { Vec::new() } else { body.into_bytes() };
+    HttpResponse { status_code: 200, headers, body }
+}
+
+fn http_error(status_code: u16, message: &str) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![
+            ("Content-Type".to_string(), "text/plain; charset=utf-8".to_string()),
+            ("Cache-Control".to_string(), "no-store".to_string()),
+        ],
+        body: message.as_bytes().to_vec(),
+    }
+}
+
+// compare tokens without returning early on the first differing byte
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// 64-bit FNV-1a hash, used for ETags
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
     })
 }
 
-// get total transportation cost
-#[ic_cdk::query]
-fn get_total_transportation_cost(trip_id: u64) -> Result<f64,Error> {
-    TRIP_STR.with(|service| {
-        let trip = service
-            .borrow_mut()
-            .get(&trip_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
+fn render_trip_html(trip: &Trip) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n</head>\n<body>\n", html_escape(&trip.name)));
+    html.push_str(&format!("<h1>{}</h1>\n", html_escape(&trip.name)));
+    html.push_str(&format!(
+        "<p>{} &ndash; {} &middot; budget {:.2}</p>\n",
+        html_escape(&trip.start_date),
+        html_escape(&trip.end_date),
+        trip.budget
+    ));
 
-        let mut total_cost = 0.0;
-        for transportation in trip.transportation.iter() {
-            total_cost += transportation.cost;
+    if !trip.destinations.is_empty() {
+        html.push_str("<h2>Destinations</h2>\n<ol>\n");
+        for stop in trip.destinations.iter() {
+            html.push_str(&format!(
+                "<li>{} ({} &ndash; {})</li>\n",
+                html_escape(&location_label(&stop.location)),
+                html_escape(&stop.arrival_date),
+                html_escape(&stop.departure_date)
+            ));
         }
+        html.push_str("</ol>\n");
+    }
 
-        Ok(total_cost)
-    })
+    if !trip.schedule.is_empty() {
+        html.push_str("<h2>Schedule</h2>\n<ul>\n");
+        for slot in trip.schedule.iter() {
+            if let Some(activity) = trip.activities.iter().find(|a| a.id == slot.activity_id) {
+                html.push_str(&format!(
+                    "<li>{} {} &ndash; {} ({}h)</li>\n",
+                    html_escape(&slot.date),
+                    html_escape(&slot.start_time),
+                    html_escape(&activity.name),
+                    activity.duration
+                ));
+            }
+        }
+        html.push_str("</ul>\n");
+    }
+
+    if !trip.transportation.is_empty() {
+        html.push_str("<h2>Transportation</h2>\n<ul>\n");
+        for leg in trip.transportation.iter() {
+            let name = |id: u64| {
+                LOCATION_STR
+                    .with(|m| m.borrow().get(&id))
+                    .map(|l| location_label(&l))
+                    .unwrap_or_else(|| format!("location {}", id))
+            };
+            html.push_str(&format!(
+                "<li>{} {}: {} &rarr; {} ({:.2})</li>\n",
+                html_escape(&leg.date),
+                html_escape(&leg.type_),
+                html_escape(&name(leg.from)),
+                html_escape(&name(leg.to)),
+                leg.cost
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
 }
 
-// get total activity cost
-#[ic_cdk::query]
-fn get_total_activity_cost(trip_id: u64) -> Result<f64,Error> {
-    TRIP_STR.with(|service| {
-        let trip = service
-            .borrow_mut()
-            .get(&trip_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
 
-        let mut total_cost = 0.0;
-        for activity in trip.activities.iter() {
-            total_cost += activity.cost;
+fn render_trip_ics(trip: &Trip) -> String {
+    let stamp = format_ics_timestamp(ic_cdk::api::time());
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//trip_planner//trip_planner_backend//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", ics_escape(&trip.name)),
+    ];
+
+    // all-day event spanning the whole trip, DTEND is exclusive
+    if let (Ok(start), Ok(end)) = (parse_date(&trip.start_date), parse_date(&trip.end_date)) {
+        let places = trip
+            .destinations
+            .iter()
+            .map(|stop| location_label(&stop.location))
+            .collect::<Vec<_>>()
+            .join(" / ");
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:trip-{}@trip_planner", trip.id));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", format_ics_date(start)));
+        lines.push(format!("DTEND;VALUE=DATE:{}", format_ics_date(end + 1)));
+        lines.push(format!("SUMMARY:{}", ics_escape(&trip.name)));
+        if !places.is_empty() {
+            lines.push(format!("LOCATION:{}", ics_escape(&places)));
         }
+        lines.push("END:VEVENT".to_string());
+    }
 
-        Ok(total_cost)
-    })
+    for leg in trip.transportation.iter() {
+        let (day, minutes) = match parse_date_time(&leg.date) {
+            Ok(parsed) => parsed,
+            Err(_) => continue,
+        };
+        let from = LOCATION_STR.with(|m| m.borrow().get(&leg.from));
+        let to = LOCATION_STR.with(|m| m.borrow().get(&leg.to));
+        let summary = format!(
+            "{}: {} → {}",
+            leg.type_,
+            from.as_ref().map(|l| l.name.clone()).unwrap_or_else(|| format!("location {}", leg.from)),
+            to.as_ref().map(|l| l.name.clone()).unwrap_or_else(|| format!("location {}", leg.to)),
+        );
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:trip-{}-transport-{}@trip_planner", trip.id, leg.id));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        match minutes {
+            Some(minutes) => lines.push(format!("DTSTART:{}", format_ics_date_time(day, minutes))),
+            None => {
+                lines.push(format!("DTSTART;VALUE=DATE:{}", format_ics_date(day)));
+                lines.push(format!("DTEND;VALUE=DATE:{}", format_ics_date(day + 1)));
+            }
+        }
+        lines.push(format!("SUMMARY:{}", ics_escape(&summary)));
+        if let Some(from) = from.as_ref() {
+            lines.push(format!("LOCATION:{}", ics_escape(&location_label(from))));
+        }
+        lines.push(format!("DESCRIPTION:{}", ics_escape(&format!("Cost: {:.2}", leg.cost))));
+        lines.push("END:VEVENT".to_string());
+    }
+
+    for slot in trip.schedule.iter() {
+        let activity = match trip.activities.iter().find(|a| a.id == slot.activity_id) {
+            Some(activity) => activity,
+            None => continue,
+        };
+        let (day, minutes) = match (parse_date(&slot.date), parse_time(&slot.start_time)) {
+            (Ok(day), Ok(minutes)) => (day, minutes),
+            _ => continue,
+        };
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:trip-{}-activity-{}@trip_planner", trip.id, activity.id));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART:{}", format_ics_date_time(day, minutes)));
+        lines.push(format!("DURATION:PT{}H", activity.duration));
+        lines.push(format!("SUMMARY:{}", ics_escape(&activity.name)));
+        if let Some(location) = LOCATION_STR.with(|m| m.borrow().get(&activity.location)) {
+            lines.push(format!("LOCATION:{}", ics_escape(&location_label(&location))));
+        }
+        if !activity.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", ics_escape(&activity.description)));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines.iter() {
+        ics.push_str(&ics_fold(line));
+        ics.push_str("\r\n");
+    }
+    ics
 }
 
-// get total cost
-#[ic_cdk::query]
-fn get_total_cost(trip_id: u64) -> Result<f64,Error> {
-    TRIP_STR.with(|service| {
-        let trip = service
-            .borrow_mut()
-            .get(&trip_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
+// "name, country" label used wherever a location is displayed
+fn location_label(location: &Location) -> String {
+    if location.country.is_empty() {
+        location.name.clone()
+    } else {
+        format!("{}, {}", location.name, location.country)
+    }
+}
 
-        let mut total_cost = 0.0;
-        for activity in trip.activities.iter() {
-            total_cost += activity.cost;
+// escape TEXT values as described in RFC 5545 section 3.3.11
+fn ics_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
         }
+    }
+    escaped
+}
 
-        for transportation in trip.transportation.iter() {
-            total_cost += transportation.cost;
+// fold content lines longer than 75 octets, without splitting a UTF-8 character
+fn ics_fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
         }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
 
-        Ok(total_cost)
-    })
+fn format_ics_date(day: i64) -> String {
+    format_date(day).replace('-', "")
 }
 
-// get total duration
-#[ic_cdk::query]
-fn get_total_duration(trip_id: u64) -> Result<u32,Error> {
-    TRIP_STR.with(|service| {
-        let trip = service
-            .borrow_mut()
-            .get(&trip_id)
-            .ok_or(Error::NotFound {
-                msg: format!("Trip with the id={} not found", trip_id),
-            })?;
+fn format_ics_date_time(day: i64, minutes: u32) -> String {
+    format!("{}T{:02}{:02}00", format_ics_date(day), minutes / 60, minutes % 60)
+}
 
-        let mut total_duration = 0;
-        for activity in trip.activities.iter() {
-            total_duration += activity.duration;
+// UTC timestamp from nanoseconds since the epoch, as used by DTSTAMP
+fn format_ics_timestamp(nanos: u64) -> String {
+    let seconds = nanos / 1_000_000_000;
+    let day = (seconds / 86_400) as i64;
+    let rest = seconds % 86_400;
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_ics_date(day),
+        rest / 3600,
+        (rest % 3600) / 60,
+        rest % 60
+    )
+}
+
+// trimmed, lowercase and without duplicates
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
         }
+    }
+    normalized
+}
 
-        Ok(total_duration)
-    })
+// Check that an activity is in season on `day` and open on that weekday. With a
+// start time, the whole activity (start + duration) must fit in one opening window.
+fn check_activity_availability(activity: &Activity, day: i64, start: Option<u32>) -> Result<(),Error> {
+    if let Some(season) = activity.season.as_ref() {
+        let (_, month, _) = civil_from_days(day);
+        let month = month as u8;
+        let in_season = if season.from_month <= season.to_month {
+            season.from_month <= month && month <= season.to_month
+        } else {
+            month >= season.from_month || month <= season.to_month
+        };
+        if !in_season {
+            return Err(Error::InvalidInput {
+                msg: format!("{} is out of season on {}", activity.name, format_date(day)),
+            });
+        }
+    }
+
+    if activity.opening_hours.is_empty() {
+        return Ok(());
+    }
+    let weekday = weekday_of(day);
+    let mut open_that_day = false;
+    for hours in activity.opening_hours.iter().filter(|h| h.weekday == weekday) {
+        open_that_day = true;
+        let start = match start {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+        let end = activity
+            .duration
+            .checked_mul(60)
+            .and_then(|minutes| start.checked_add(minutes))
+            .ok_or(Error::InvalidInput {
+                msg: format!("{} does not fit in a day when starting at that time", activity.name),
+            })?;
+        if parse_time(&hours.open)? <= start && end <= parse_time(&hours.close)? {
+            return Ok(());
+        }
+    }
+
+    let msg = if open_that_day {
+        format!("{} is not open long enough at that time on {:?}", activity.name, weekday)
+    } else {
+        format!("{} is closed on {:?}", activity.name, weekday)
+    };
+    Err(Error::InvalidInput { msg })
+}
+
+// 1970-01-01 was a Thursday
+fn weekday_of(day: i64) -> Weekday {
+    match (day + 3).rem_euclid(7) {
+        0 => Weekday::Monday,
+        1 => Weekday::Tuesday,
+        2 => Weekday::Wednesday,
+        3 => Weekday::Thursday,
+        4 => Weekday::Friday,
+        5 => Weekday::Saturday,
+        _ => Weekday::Sunday,
+    }
+}
+
+// parse a "YYYY-MM-DD" date into the number of days since 1970-01-01
+fn parse_date(date: &str) -> Result<i64,Error> {
+    let invalid = || Error::InvalidInput {
+        msg: format!("Invalid date '{}', expected YYYY-MM-DD", date),
+    };
+    let parts = date.trim().split('-').collect::<Vec<_>>();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return Err(invalid());
+    }
+    let year = parts[0].parse::<i64>().map_err(|_| invalid())?;
+    let month = parts[1].parse::<u32>().map_err(|_| invalid())?;
+    let day = parts[2].parse::<u32>().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    Ok(days_from_civil(year, month, day))
+}
+
+// parse a "HH:MM" time into minutes after midnight
+fn parse_time(time: &str) -> Result<u32,Error> {
+    let invalid = || Error::InvalidInput {
+        msg: format!("Invalid time '{}', expected HH:MM", time),
+    };
+    let (hours, minutes) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let hours = hours.parse::<u32>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<u32>().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+// parse "YYYY-MM-DD" with an optional "THH:MM" or " HH:MM" time part
+fn parse_date_time(value: &str) -> Result<(i64, Option<u32>),Error> {
+    match value.trim().split_once(['T', ' ']) {
+        Some((date, time)) => Ok((parse_date(date)?, Some(parse_time(time)?))),
+        None => Ok((parse_date(value)?, None)),
+    }
+}
+
+// format days since 1970-01-01 as "YYYY-MM-DD"
+fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
 }
 
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
 
 #[derive(candid::CandidType, Deserialize, Serialize)]
 enum  Error {
     NotFound { msg: String },
+    InvalidInput { msg: String },
+    AlreadyExists { msg: String },
+    Conflict { msg: String },
+    Unauthorized { msg: String },
 }
 
 // Export the candid interface
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_date_counts_days_from_the_epoch() {
+        assert_eq!(parse_date("1970-01-01").ok(), Some(0));
+        assert_eq!(parse_date("2000-03-01").ok(), Some(11_017));
+        assert_eq!(parse_date(" 2024-02-29 ").ok(), Some(19_782));
+        assert_eq!(parse_date("1969-12-31").ok(), Some(-1));
+    }
+
+    #[test]
+    fn parse_date_rejects_invalid_dates() {
+        for date in ["", "2024-2-01", "2024-02-30", "2023-02-29", "1900-02-29", "2024-13-01", "2024-00-10", "2024/01/01"] {
+            assert!(parse_date(date).is_err(), "{} should be rejected", date);
+        }
+    }
+
+    #[test]
+    fn civil_dates_round_trip() {
+        for days in [-719_468, -1, 0, 59, 60, 11_016, 11_017, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(format_date(-1), "1969-12-31");
+        assert_eq!(format_date(11_017), "2000-03-01");
+    }
+
+    #[test]
+    fn ics_escape_escapes_text_values() {
+        assert_eq!(ics_escape("a,b;c\\d"), r"a\,b\;c\\d");
+        assert_eq!(ics_escape("line\r\nbreak"), "line\\nbreak");
+        assert_eq!(ics_escape("plain"), "plain");
+    }
+
+    #[test]
+    fn ics_fold_limits_lines_to_75_octets() {
+        assert_eq!(ics_fold(&"a".repeat(75)), "a".repeat(75));
+
+        let folded = ics_fold(&"a".repeat(160));
+        let lines = folded.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(lines.iter().map(|l| l.len()).collect::<Vec<_>>(), vec![75, 75, 12]);
+        assert!(lines[1..].iter().all(|l| l.starts_with(' ')));
+        assert_eq!(folded.replace("\r\n ", ""), "a".repeat(160));
+    }
+
+    #[test]
+    fn ics_fold_keeps_utf8_characters_whole() {
+        let line = format!("{}é{}", "a".repeat(74), "b".repeat(10));
+        let folded = ics_fold(&line);
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert!(folded.starts_with(&format!("{}\r\n é", "a".repeat(74))));
+    }
+
+    fn csv(text: &str) -> Vec<(u64, Vec<String>)> {
+        parse_csv(text).ok().expect("valid CSV")
+    }
+
+    fn fields(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_csv_splits_records_and_fields() {
+        assert_eq!(
+            csv("name,country\nRome,Italy\r\nOslo,Norway"),
+            vec![(1, fields(&["name", "country"])), (2, fields(&["Rome", "Italy"])), (3, fields(&["Oslo", "Norway"]))]
+        );
+        assert_eq!(csv("a,,\n"), vec![(1, fields(&["a", "", ""]))]);
+        assert!(csv("").is_empty());
+    }
+
+    #[test]
+    fn parse_csv_handles_quoted_fields() {
+        assert_eq!(
+            csv("\u{feff}\"Rome, Lazio\",\"say \"\"ciao\"\"\"\n\"two\nlines\",x\nnext,row\n"),
+            vec![
+                (1, fields(&["Rome, Lazio", "say \"ciao\""])),
+                (2, fields(&["two\nlines", "x"])),
+                (4, fields(&["next", "row"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_csv_rejects_unterminated_quotes() {
+        match parse_csv("a,b\n\"open,c\nd") {
+            Err(Error::InvalidInput { msg }) => assert!(msg.contains("line 2"), "{}", msg),
+            _ => panic!("unterminated quote should be rejected"),
+        }
+    }
+
+    // places on a line at the given positions, the cost of a leg is the distance
+    fn line_costs(positions: &[f64]) -> Vec<Vec<f64>> {
+        positions.iter().map(|a| positions.iter().map(|b| (a - b).abs()).collect()).collect()
+    }
+
+    fn path_cost(cost: &[Vec<f64>], order: &[usize]) -> f64 {
+        order.windows(2).map(|w| cost[w[0]][w[1]]).sum()
+    }
+
+    #[test]
+    fn shortest_path_exact_finds_the_optimal_order() {
+        let cost = line_costs(&[0.0, 30.0, 10.0, 20.0]);
+        let order = shortest_path_exact(&cost, None, None);
+        assert!(order == vec![0, 2, 3, 1] || order == vec![1, 3, 2, 0], "{:?}", order);
+        assert_eq!(shortest_path_exact(&cost, Some(0), None), vec![0, 2, 3, 1]);
+        assert_eq!(shortest_path_exact(&cost, Some(2), Some(3)), vec![2, 0, 1, 3]);
+        assert_eq!(shortest_path_exact(&cost, None, Some(0)), vec![1, 3, 2, 0]);
+    }
+
+    #[test]
+    fn shortest_path_exact_handles_a_single_stop() {
+        assert_eq!(shortest_path_exact(&[vec![0.0]], Some(0), None), vec![0]);
+    }
+
+    #[test]
+    fn shortest_path_two_opt_untangles_crossed_legs() {
+        let cost = line_costs(&[0.0, 30.0, 10.0, 20.0, 40.0]);
+        let order = shortest_path_two_opt(&cost, true, true);
+        assert_eq!(order, vec![0, 2, 3, 1, 4]);
+        assert_eq!(path_cost(&cost, &order), 40.0);
+    }
+
+    #[test]
+    fn shortest_path_two_opt_keeps_fixed_ends() {
+        let cost = line_costs(&[20.0, 0.0, 10.0, 30.0]);
+        let order = shortest_path_two_opt(&cost, true, false);
+        assert_eq!(order[0], 0);
+        assert!(path_cost(&cost, &order) <= path_cost(&cost, &[0, 1, 2, 3]));
+
+        let order = shortest_path_two_opt(&cost, false, true);
+        assert_eq!(order[3], 3);
+        assert_eq!(path_cost(&cost, &order), 30.0);
+    }
+
+    fn candidate(id: u64, score: f64, duration: u32, cost: f64) -> (f64, Activity) {
+        let activity = Activity {
+            id,
+            name: format!("activity {}", id),
+            duration,
+            cost,
+            description: String::new(),
+            location: 1,
+            category: ActivityCategory::Other,
+            tags: Vec::new(),
+            opening_hours: Vec::new(),
+            season: None,
+            version: 1,
+            created_at: 0,
+            updated_at: 0,
+            created_by: Principal::anonymous(),
+            updated_by: Principal::anonymous(),
+        };
+        (score, activity)
+    }
+
+    #[test]
+    fn knapsack_by_cost_respects_hours_and_budget() {
+        let candidates = vec![candidate(1, 5.0, 4, 30.0), candidate(2, 4.0, 3, 20.0), candidate(3, 3.0, 2, 20.0)];
+        let best = knapsack_by_cost(&candidates, 5, 50, 1.0);
+        assert_eq!(best.len(), 51);
+        // within 5 hours, activities 2 and 3 together score more than activity 1
+        assert_eq!(best[50], (7.0, 0b110));
+        assert_eq!(best[30], (5.0, 0b001));
+        assert_eq!(best[20], (4.0, 0b010));
+        assert_eq!(best[19], (0.0, 0));
+    }
+
+    #[test]
+    fn knapsack_by_cost_rounds_costs_up_to_the_unit() {
+        let candidates = vec![candidate(1, 2.0, 1, 15.0), candidate(2, 1.0, 1, 10.0)];
+        let best = knapsack_by_cost(&candidates, 8, 2, 10.0);
+        // 15 takes two steps of 10, so both together need three
+        assert_eq!(best[1], (1.0, 0b10));
+        assert_eq!(best[2], (2.0, 0b01));
+    }
+
+    #[test]
+    fn knapsack_by_cost_skips_candidates_that_never_fit() {
+        let candidates = vec![candidate(1, 9.0, 10, 1.0), candidate(2, 1.0, 1, 1.0)];
+        let best = knapsack_by_cost(&candidates, 4, 10, 1.0);
+        assert_eq!(best[10], (1.0, 0b10));
+    }
+
+    #[test]
+    fn status_transitions_follow_the_lifecycle() {
+        use TripStatus::*;
+        let allowed = [
+            (Draft, Planned),
+            (Planned, Draft),
+            (Planned, Booked),
+            (Booked, Planned),
+            (Planned, InProgress),
+            (Booked, InProgress),
+            (InProgress, Completed),
+            (Draft, Cancelled),
+            (Planned, Cancelled),
+            (Booked, Cancelled),
+            (InProgress, Cancelled),
+        ];
+        let all = [Draft, Planned, Booked, InProgress, Completed, Cancelled];
+        for from in all {
+            for to in all {
+                assert_eq!(
+                    status_transition_allowed(from, to),
+                    allowed.contains(&(from, to)),
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    fn trip_in(status: TripStatus) -> Trip {
+        Trip {
+            id: 1,
+            name: "trip".to_string(),
+            start_date: "2024-05-01".to_string(),
+            end_date: "2024-05-03".to_string(),
+            destinations: Vec::new(),
+            activities: Vec::new(),
+            budget: 0.0,
+            transportation: Vec::new(),
+            schedule: Vec::new(),
+            accommodations: Vec::new(),
+            bookings: Vec::new(),
+            status,
+            version: 1,
+            created_at: 0,
+            updated_at: 0,
+            created_by: Principal::anonymous(),
+            updated_by: Principal::anonymous(),
+        }
+    }
+
+    #[test]
+    fn only_unfinished_trips_are_editable() {
+        use TripStatus::*;
+        for status in [Draft, Planned, Booked, InProgress] {
+            assert!(check_trip_editable(&trip_in(status)).is_ok(), "{:?}", status);
+        }
+        for status in [Completed, Cancelled] {
+            assert!(matches!(check_trip_editable(&trip_in(status)), Err(Error::InvalidInput { .. })), "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn parse_relative_days_accepts_day_and_week_offsets() {
+        assert_eq!(parse_relative_days("-30 days").ok(), Some(-30));
+        assert_eq!(parse_relative_days("+2 Weeks").ok(), Some(14));
+        assert_eq!(parse_relative_days("1 day").ok(), Some(1));
+        assert_eq!(parse_relative_days("-3650 days").ok(), Some(-3650));
+    }
+
+    #[test]
+    fn parse_relative_days_rejects_out_of_range_offsets() {
+        for value in ["3651 days", "-522 weeks", "-9223372036854775808 days", "9223372036854775807 weeks", "2 months", "days", "1 day ago"] {
+            assert!(parse_relative_days(value).is_err(), "{} should be rejected", value);
+        }
+    }
+}